@@ -0,0 +1,426 @@
+use bevy::prelude::*;
+use crate::items::{spawn_coin, Coin, HeldItem, KartHit, OutOfRound, Roulette};
+use crate::logic::{GameMode, PlayerStats, RacePhase, RacePosition};
+use crate::menu::Screen;
+use crate::player::{FollowCamera, Kart, LocalPlayer};
+use crate::track::{LoadTrack, TrackDefinition};
+
+// Timed rounds in the arena.
+// Balloon battle: three balloons each, every item hit pops one, last kart with balloons left wins.
+// Coin runners: coins keep appearing, hits make karts drop some, most coins at the end wins.
+// When time runs out the best score wins, a tie at the top is a draw.
+pub struct BattlePlugin;
+
+impl Plugin for BattlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BattleRound>()
+           .add_systems(OnEnter(Screen::Race), (start_round, spawn_round_hud).run_if(in_arena))
+           .add_systems(OnEnter(Screen::Race), give_balloons.run_if(is_battle))
+           .add_systems(OnExit(Screen::Race), remove_balloons)
+           .add_systems(Update, (
+               (pop_balloons, rank_by_balloons, last_balloons_standing).chain().run_if(is_battle),
+               (spawn_field_coins, drop_coins, rank_by_coins).chain().run_if(is_coin_runners),
+               tick_round.run_if(in_state(RacePhase::Racing)),
+               update_round_hud,
+               next_round_input,
+           ).chain().run_if(in_state(Screen::Race).and(in_arena)));
+    }
+}
+
+pub const ARENA_TRACK: &str = "arena";
+pub const START_BALLOONS: usize = 3;
+pub const ROUND_TIME: f32 = 120.0;
+// Can't lose another balloon right after one popped
+const POP_COOLDOWN: f32 = 2.0;
+// Coin runners: a new coin this often while fewer than FIELD_COINS are out, spread over the arena
+const COIN_INTERVAL: f32 = 0.8;
+const FIELD_COINS: usize = 30;
+const COIN_AREA: f32 = 22.0;
+// Coins lost per hit, scattered this far around the kart
+const COINS_DROPPED: usize = 3;
+const DROP_RADIUS: f32 = 3.0;
+
+const BALLOON_COLORS: [Color; 4] = [
+    Color::srgb(0.9, 0.15, 0.15),
+    Color::srgb(0.15, 0.4, 0.95),
+    Color::srgb(0.2, 0.8, 0.25),
+    Color::srgb(0.95, 0.8, 0.1),
+];
+
+#[derive(Component)]
+pub struct Balloons {
+    pub count: usize,
+    pub cooldown: f32,
+}
+
+// One of the balloons tied to a kart, `index` 0 pops last
+#[derive(Component)]
+struct Balloon {
+    index: usize,
+}
+
+#[derive(Resource, Default)]
+pub struct BattleRound {
+    pub remaining: f32,
+    pub over: bool,
+    // None with the round over is a draw
+    pub winner: Option<Entity>,
+    // Time to the next field coin
+    next_coin: f32,
+}
+
+#[derive(Component)]
+struct BalloonIcon {
+    kart: Entity,
+    index: usize,
+}
+
+#[derive(Component)]
+struct BattleTimer;
+
+#[derive(Component)]
+struct BattleBanner;
+
+fn in_arena(mode: Res<GameMode>) -> bool {
+    mode.is_arena()
+}
+
+fn is_battle(mode: Res<GameMode>) -> bool {
+    *mode == GameMode::Battle
+}
+
+fn is_coin_runners(mode: Res<GameMode>) -> bool {
+    *mode == GameMode::CoinRunners
+}
+
+fn start_round(mut round: ResMut<BattleRound>, mut load_events: EventWriter<LoadTrack>) {
+    *round = BattleRound { remaining: ROUND_TIME, ..default() };
+    load_events.send(LoadTrack(ARENA_TRACK.to_string()));
+}
+
+fn give_balloons(
+    mut commands: Commands,
+    kart_query: Query<(Entity, Option<&LocalPlayer>), With<Kart>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mesh = meshes.add(Sphere::new(0.25));
+    for (i, (kart, local)) in kart_query.iter().enumerate() {
+        let color = BALLOON_COLORS[local.map_or(i, |local| local.index) % BALLOON_COLORS.len()];
+        let material = materials.add(StandardMaterial { base_color: color, ..default() });
+
+        commands.entity(kart).insert(Balloons { count: START_BALLOONS, cooldown: 0.0 }).with_children(|parent| {
+            // Above and behind the driver, over the KartVisual
+            for index in 0..START_BALLOONS {
+                parent.spawn((
+                    Mesh3d(mesh.clone()),
+                    MeshMaterial3d(material.clone()),
+                    Transform::from_xyz((index as f32 - 1.0) * 0.4, 1.5 + (index % 2) as f32 * 0.15, 0.5),
+                    Balloon { index },
+                ));
+            }
+        });
+    }
+}
+
+fn remove_balloons(
+    mut commands: Commands,
+    balloon_query: Query<Entity, With<Balloon>>,
+    kart_query: Query<Entity, With<Balloons>>,
+) {
+    for entity in balloon_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for kart in kart_query.iter() {
+        commands.entity(kart).remove::<(Balloons, OutOfRound)>();
+    }
+}
+
+fn pop_balloons(
+    mut commands: Commands,
+    mut hit_events: EventReader<KartHit>,
+    mut kart_query: Query<(&Kart, &mut Balloons, &mut HeldItem, &Children)>,
+    balloon_query: Query<&Balloon>,
+    round: Res<BattleRound>,
+    time: Res<Time>,
+) {
+    for (_, mut balloons, _, _) in kart_query.iter_mut() {
+        balloons.cooldown = (balloons.cooldown - time.delta_secs()).max(0.0);
+    }
+    if round.over {
+        hit_events.clear();
+        return;
+    }
+
+    for hit in hit_events.read() {
+        let Ok((kart, mut balloons, mut held, children)) = kart_query.get_mut(hit.kart) else { continue; };
+        // Stars protect the balloons too
+        if balloons.count == 0 || balloons.cooldown > 0.0 || kart.star_timer > 0.0 {
+            continue;
+        }
+        balloons.count -= 1;
+        balloons.cooldown = POP_COOLDOWN;
+        for child in children.iter() {
+            if balloon_query.get(*child).is_ok_and(|balloon| balloon.index == balloons.count) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+        if balloons.count == 0 {
+            *held = HeldItem::default();
+            commands.entity(hit.kart).insert(OutOfRound);
+            info!("{:?} is out of balloons", hit.kart);
+        } else {
+            info!("Balloon popped, {} left", balloons.count);
+        }
+    }
+}
+
+// Fewer balloons is a worse place: better items from the boxes, and red shells chase whoever is just ahead
+fn rank_by_balloons(mut kart_query: Query<(Entity, &Balloons, &mut RacePosition)>) {
+    let mut ranking: Vec<(Entity, usize)> = kart_query.iter().map(|(entity, balloons, _)| (entity, balloons.count)).collect();
+    ranking.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    for (place, (entity, _)) in ranking.into_iter().enumerate() {
+        if let Ok((_, _, mut position)) = kart_query.get_mut(entity) {
+            if position.0 != place + 1 {
+                position.0 = place + 1;
+            }
+        }
+    }
+}
+
+fn last_balloons_standing(
+    mut round: ResMut<BattleRound>,
+    kart_query: Query<(Entity, &Balloons)>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+) {
+    let alive: Vec<Entity> = kart_query.iter().filter(|(_, balloons)| balloons.count > 0).map(|(entity, _)| entity).collect();
+    if !round.over && kart_query.iter().count() > 1 && alive.len() <= 1 {
+        end_round(&mut round, alive.first().copied(), &mut next_phase);
+    }
+}
+
+fn tick_round(
+    mut round: ResMut<BattleRound>,
+    kart_query: Query<(Entity, &PlayerStats, Option<&Balloons>)>,
+    mode: Res<GameMode>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+    time: Res<Time>,
+) {
+    if round.over {
+        return;
+    }
+    round.remaining = (round.remaining - time.delta_secs()).max(0.0);
+    if round.remaining > 0.0 {
+        return;
+    }
+
+    let score = |stats: &PlayerStats, balloons: Option<&Balloons>| match *mode {
+        GameMode::Battle => balloons.map_or(0, |balloons| balloons.count),
+        _ => stats.coin_count,
+    };
+    let best = kart_query.iter().map(|(_, stats, balloons)| score(stats, balloons)).max().unwrap_or(0);
+    let leaders: Vec<Entity> = kart_query.iter()
+        .filter(|(_, stats, balloons)| score(stats, *balloons) == best)
+        .map(|(entity, _, _)| entity)
+        .collect();
+    let winner = if leaders.len() == 1 { Some(leaders[0]) } else { None };
+    end_round(&mut round, winner, &mut next_phase);
+}
+
+fn end_round(round: &mut BattleRound, winner: Option<Entity>, next_phase: &mut NextState<RacePhase>) {
+    round.over = true;
+    round.winner = winner;
+    info!("Round over, winner: {:?}", winner);
+    // Engines off until the next round
+    next_phase.set(RacePhase::Lobby);
+}
+
+fn spawn_field_coins(
+    mut commands: Commands,
+    mut round: ResMut<BattleRound>,
+    coin_query: Query<(), With<Coin>>,
+    track: Res<TrackDefinition>,
+    mut roulette: ResMut<Roulette>,
+    asset_server: Res<AssetServer>,
+    phase: Res<State<RacePhase>>,
+    time: Res<Time>,
+) {
+    if round.over || *phase.get() != RacePhase::Racing {
+        return;
+    }
+    round.next_coin -= time.delta_secs();
+    if round.next_coin > 0.0 || coin_query.iter().count() >= FIELD_COINS {
+        return;
+    }
+    round.next_coin = COIN_INTERVAL;
+
+    let x = (roulette.next_f32() * 2.0 - 1.0) * COIN_AREA;
+    let z = (roulette.next_f32() * 2.0 - 1.0) * COIN_AREA;
+    let pos = Vec3::new(x, 1.0, z);
+    // Not inside a wall, try again next time
+    let blocked = track.walls.iter()
+        .any(|wall| pos.cmpge(Vec3::from(wall.min) - 0.5).all() && pos.cmple(Vec3::from(wall.max) + 0.5).all());
+    if !blocked {
+        spawn_coin(&mut commands, &asset_server, pos);
+    }
+}
+
+fn drop_coins(
+    mut commands: Commands,
+    mut hit_events: EventReader<KartHit>,
+    mut kart_query: Query<(&Kart, &Transform, &mut PlayerStats)>,
+    round: Res<BattleRound>,
+    asset_server: Res<AssetServer>,
+) {
+    for hit in hit_events.read() {
+        let Ok((kart, transform, mut stats)) = kart_query.get_mut(hit.kart) else { continue; };
+        if round.over || kart.star_timer > 0.0 {
+            continue;
+        }
+        let dropped = stats.coin_count.min(COINS_DROPPED);
+        stats.coin_count -= dropped;
+        for i in 0..dropped {
+            let angle = i as f32 / COINS_DROPPED as f32 * std::f32::consts::TAU;
+            let offset = Vec3::new(angle.cos(), 0.0, angle.sin()) * DROP_RADIUS;
+            spawn_coin(&mut commands, &asset_server, Vec3::new(transform.translation.x, 1.0, transform.translation.z) + offset);
+        }
+        if dropped > 0 {
+            info!("{:?} dropped {} coins", hit.kart, dropped);
+        }
+    }
+}
+
+// Like balloons, fewer coins is a worse place for items and red shells
+fn rank_by_coins(mut kart_query: Query<(Entity, &PlayerStats, &mut RacePosition)>) {
+    let mut ranking: Vec<(Entity, usize)> = kart_query.iter().map(|(entity, stats, _)| (entity, stats.coin_count)).collect();
+    ranking.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    for (place, (entity, _)) in ranking.into_iter().enumerate() {
+        if let Ok((_, _, mut position)) = kart_query.get_mut(entity) {
+            if position.0 != place + 1 {
+                position.0 = place + 1;
+            }
+        }
+    }
+}
+
+// Balloons left top left (battle only), round timer top center, one HUD per player screen
+fn spawn_round_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<(Entity, &FollowCamera)>,
+    mode: Res<GameMode>,
+) {
+    let font = asset_server.load("fonts/HK.ttf");
+
+    for (camera, follow) in camera_query.iter() {
+        let color = BALLOON_COLORS[follow.player % BALLOON_COLORS.len()];
+        commands
+            .spawn((
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                TargetCamera(camera),
+                StateScoped(Screen::Race),
+            ))
+            .with_children(|parent| {
+                parent.spawn(Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(30.0),
+                    left: Val::Px(30.0),
+                    column_gap: Val::Px(10.0),
+                    ..default()
+                }).with_children(|row| {
+                    let balloons = if *mode == GameMode::Battle { START_BALLOONS } else { 0 };
+                    for index in 0..balloons {
+                        row.spawn((
+                            Node { width: Val::Px(36.0), height: Val::Px(44.0), ..default() },
+                            BorderRadius::MAX,
+                            BackgroundColor(color),
+                            BalloonIcon { kart: follow.target, index },
+                        ));
+                    }
+                });
+
+                parent.spawn((
+                    Text::new(""),
+                    TextFont { font: font.clone(), font_size: 48.0, ..default() },
+                    TextColor(Color::WHITE),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(30.0),
+                        width: Val::Percent(100.0),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    TextLayout::new_with_justify(JustifyText::Center),
+                    BattleTimer,
+                ));
+
+                parent.spawn((
+                    Text::new(""),
+                    TextFont { font: font.clone(), font_size: 64.0, ..default() },
+                    TextColor(Color::srgb(1.0, 0.85, 0.0)),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        top: Val::Percent(35.0),
+                        width: Val::Percent(100.0),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    TextLayout::new_with_justify(JustifyText::Center),
+                    BattleBanner,
+                ));
+            });
+    }
+}
+
+fn update_round_hud(
+    round: Res<BattleRound>,
+    kart_query: Query<(Option<&Balloons>, Option<&LocalPlayer>)>,
+    mut icon_query: Query<(&mut Node, &BalloonIcon)>,
+    mut timer_query: Query<&mut Text, (With<BattleTimer>, Without<BattleBanner>)>,
+    mut banner_query: Query<&mut Text, (With<BattleBanner>, Without<BattleTimer>)>,
+) {
+    for (mut node, icon) in icon_query.iter_mut() {
+        let count = kart_query.get(icon.kart).ok().and_then(|(balloons, _)| balloons).map_or(0, |balloons| balloons.count);
+        let display = if icon.index < count { Display::Flex } else { Display::None };
+        if node.display != display {
+            node.display = display;
+        }
+    }
+
+    let seconds = round.remaining.ceil() as u32;
+    for mut text in timer_query.iter_mut() {
+        text.0 = format!("{}:{:02}", seconds / 60, seconds % 60);
+    }
+
+    let banner = if round.over {
+        let winner = round.winner.and_then(|winner| kart_query.get(winner).ok()).map(|(_, local)| local);
+        let verdict = match winner {
+            Some(Some(local)) => format!("Player {} wins!", local.index + 1),
+            Some(None) => "Winner!".to_string(),
+            None => "Draw!".to_string(),
+        };
+        format!("{}\nEnter: next round", verdict)
+    } else {
+        String::new()
+    };
+    for mut text in banner_query.iter_mut() {
+        if text.0 != banner {
+            text.0 = banner.clone();
+        }
+    }
+}
+
+// Back through the select screen, everyone can change karts between rounds
+fn next_round_input(
+    round: Res<BattleRound>,
+    menu: Res<crate::input::MenuInput>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    if round.over && menu.confirm {
+        next_screen.set(Screen::Select);
+    }
+}
@@ -0,0 +1,310 @@
+// Lobby server: players create or join rooms by code, and once everyone is ready
+// the host's StartRace spawns a game server (`mariok-bevy --server`) for that room.
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use mariok_bevy::lobby::{LobbyEvent, LobbyPlayer, LobbyRequest, RoomInfo, CHARACTERS, DEFAULT_LOBBY_PORT, MAX_ROOM_PLAYERS};
+use mariok_bevy::net::{accept_websocket, read_websocket};
+use tungstenite::{Message, WebSocket};
+
+// Game servers get a UDP port and the next one for WebSockets
+const FIRST_RACE_PORT: u16 = 5100;
+
+struct Client {
+    id: u32,
+    socket: WebSocket<TcpStream>,
+    room: Option<String>,
+}
+
+struct Room {
+    info: RoomInfo,
+    race: Option<Child>,
+}
+
+struct Lobby {
+    clients: Vec<Client>,
+    rooms: Vec<Room>,
+    next_id: u32,
+    next_port: u16,
+    rng: u64,
+    public_host: String,
+    game_bin: PathBuf,
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.as_str())
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, event: &LobbyEvent) {
+    if let Ok(json) = serde_json::to_string(event) {
+        let _ = socket.send(Message::text(json));
+    }
+}
+
+impl Lobby {
+    fn room_code(&mut self) -> String {
+        loop {
+            let code: String = (0..4).map(|_| {
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 7;
+                self.rng ^= self.rng << 17;
+                (b'A' + (self.rng % 26) as u8) as char
+            }).collect();
+            if !self.rooms.iter().any(|r| r.info.code == code) {
+                return code;
+            }
+        }
+    }
+
+    fn client(&mut self, id: u32) -> Option<&mut Client> {
+        self.clients.iter_mut().find(|c| c.id == id)
+    }
+
+    fn room(&mut self, code: &str) -> Option<&mut Room> {
+        self.rooms.iter_mut().find(|r| r.info.code == code)
+    }
+
+    fn error(&mut self, id: u32, message: &str) {
+        if let Some(client) = self.client(id) {
+            send(&mut client.socket, &LobbyEvent::Error { message: message.to_string() });
+        }
+    }
+
+    fn broadcast(&mut self, code: &str, event: &LobbyEvent) {
+        for client in self.clients.iter_mut().filter(|c| c.room.as_deref() == Some(code)) {
+            send(&mut client.socket, event);
+        }
+    }
+
+    fn room_updated(&mut self, code: &str) {
+        let Some(room) = self.room(code) else { return; };
+        let event = LobbyEvent::RoomUpdated { room: room.info.clone() };
+        self.broadcast(code, &event);
+    }
+
+    fn handle(&mut self, id: u32, request: LobbyRequest) {
+        let current_room = self.client(id).and_then(|c| c.room.clone());
+
+        match request {
+            LobbyRequest::CreateRoom { name, settings } => {
+                self.leave(id);
+                let code = self.room_code();
+                let info = RoomInfo {
+                    code: code.clone(),
+                    host: id,
+                    settings,
+                    players: vec![LobbyPlayer { id, name, character: CHARACTERS[0].to_string(), ready: false }],
+                };
+                println!("Room {} created by player {}", code, id);
+                self.rooms.push(Room { info: info.clone(), race: None });
+                if let Some(client) = self.client(id) {
+                    client.room = Some(code);
+                    send(&mut client.socket, &LobbyEvent::Joined { player_id: id, room: info });
+                }
+            }
+            LobbyRequest::JoinRoom { code, name } => {
+                let code = code.to_uppercase();
+                let joinable = match self.room(&code) {
+                    None => Err("No room with that code"),
+                    Some(room) if room.race.is_some() => Err("That room is already racing"),
+                    Some(room) if room.info.players.len() >= MAX_ROOM_PLAYERS => Err("That room is full"),
+                    Some(_) => Ok(()),
+                };
+                if let Err(message) = joinable {
+                    self.error(id, message);
+                    return;
+                }
+
+                self.leave(id);
+                let Some(room) = self.room(&code) else { return; };
+                room.info.players.push(LobbyPlayer { id, name, character: CHARACTERS[0].to_string(), ready: false });
+                let info = room.info.clone();
+                if let Some(client) = self.client(id) {
+                    client.room = Some(code.clone());
+                    send(&mut client.socket, &LobbyEvent::Joined { player_id: id, room: info });
+                }
+                self.room_updated(&code);
+            }
+            LobbyRequest::UpdateSettings { settings } => {
+                let Some(code) = current_room else { return; };
+                let Some(room) = self.room(&code) else { return; };
+                if room.info.host != id {
+                    self.error(id, "Only the host can change the settings");
+                    return;
+                }
+                room.info.settings = settings;
+                // New settings, everyone confirms again
+                for player in room.info.players.iter_mut() {
+                    player.ready = false;
+                }
+                self.room_updated(&code);
+            }
+            LobbyRequest::PickCharacter { character } => {
+                let Some(code) = current_room else { return; };
+                if !CHARACTERS.contains(&character.as_str()) {
+                    return;
+                }
+                let Some(room) = self.room(&code) else { return; };
+                if let Some(player) = room.info.players.iter_mut().find(|p| p.id == id) {
+                    player.character = character;
+                }
+                self.room_updated(&code);
+            }
+            LobbyRequest::SetReady { ready } => {
+                let Some(code) = current_room else { return; };
+                let Some(room) = self.room(&code) else { return; };
+                if let Some(player) = room.info.players.iter_mut().find(|p| p.id == id) {
+                    player.ready = ready;
+                }
+                self.room_updated(&code);
+            }
+            LobbyRequest::StartRace => {
+                let Some(code) = current_room else { return; };
+                self.start_race(id, &code);
+            }
+            LobbyRequest::Leave => self.leave(id),
+        }
+    }
+
+    fn start_race(&mut self, id: u32, code: &str) {
+        let port = self.next_port;
+        let game_bin = self.game_bin.clone();
+        let public_host = self.public_host.clone();
+
+        let Some(room) = self.room(code) else { return; };
+        if room.info.host != id {
+            self.error(id, "Only the host can start the race");
+            return;
+        }
+        // The host is ready by starting
+        let host = room.info.host;
+        if !room.info.players.iter().all(|p| p.ready || p.id == host) {
+            self.error(id, "Not everyone is ready");
+            return;
+        }
+
+        let settings = room.info.settings.clone();
+        let child = Command::new(&game_bin)
+            .arg("--server")
+            .args(["--udp", &format!("0.0.0.0:{}", port)])
+            .args(["--ws", &format!("0.0.0.0:{}", port + 1)])
+            .args(["--laps", &settings.race.laps.to_string()])
+            .args(["--cc", settings.race.cc.label()])
+            .args(["--track", &settings.track])
+            .args(["--expect", &room.info.players.len().to_string()])
+            .spawn();
+
+        match child {
+            Ok(child) => {
+                println!("Room {} racing on ports {}/{}", code, port, port + 1);
+                room.race = Some(child);
+                self.next_port += 2;
+                let event = LobbyEvent::RaceStarting {
+                    udp_addr: format!("{}:{}", public_host, port),
+                    ws_addr: format!("ws://{}:{}", public_host, port + 1),
+                    settings,
+                };
+                self.broadcast(code, &event);
+            }
+            Err(err) => {
+                eprintln!("Can't start {}: {}", game_bin.display(), err);
+                self.error(id, "Couldn't start the race server");
+            }
+        }
+    }
+
+    fn leave(&mut self, id: u32) {
+        let Some(code) = self.client(id).and_then(|c| c.room.take()) else { return; };
+        let Some(room) = self.room(&code) else { return; };
+        room.info.players.retain(|p| p.id != id);
+
+        if room.info.players.is_empty() {
+            println!("Room {} closed", code);
+            self.rooms.retain(|r| r.info.code != code);
+            return;
+        }
+        // Next in line becomes host
+        if room.info.host == id {
+            room.info.host = room.info.players[0].id;
+        }
+        self.room_updated(&code);
+    }
+
+    // Race servers exit when their race is over, the room is open again
+    fn reap_races(&mut self) {
+        let mut finished = Vec::new();
+        for room in self.rooms.iter_mut() {
+            if let Some(child) = &mut room.race {
+                if !matches!(child.try_wait(), Ok(None)) {
+                    room.race = None;
+                    for player in room.info.players.iter_mut() {
+                        player.ready = false;
+                    }
+                    finished.push(room.info.code.clone());
+                }
+            }
+        }
+        for code in finished {
+            self.room_updated(&code);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let port = arg_value(&args, "--port").and_then(|p| p.parse().ok()).unwrap_or(DEFAULT_LOBBY_PORT);
+    let game_bin = arg_value(&args, "--game-bin").map(PathBuf::from)
+        .or_else(|| std::env::current_exe().ok().map(|exe| exe.with_file_name("mariok-bevy")))
+        .expect("Can't find the game binary, pass --game-bin");
+
+    let listener = TcpListener::bind(("0.0.0.0", port)).expect("Can't bind the lobby port");
+    listener.set_nonblocking(true).expect("Can't make the lobby socket non-blocking");
+    println!("Lobby listening on ws://0.0.0.0:{}", port);
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(1);
+    let mut lobby = Lobby {
+        clients: Vec::new(),
+        rooms: Vec::new(),
+        next_id: 1,
+        next_port: FIRST_RACE_PORT,
+        rng: seed | 1,
+        // What clients should connect to for races
+        public_host: arg_value(&args, "--public-host").unwrap_or("127.0.0.1").to_string(),
+        game_bin,
+    };
+
+    loop {
+        while let Ok((stream, addr)) = listener.accept() {
+            match accept_websocket(stream) {
+                Some(socket) => {
+                    lobby.clients.push(Client { id: lobby.next_id, socket, room: None });
+                    lobby.next_id += 1;
+                }
+                None => eprintln!("WebSocket handshake with {} failed", addr),
+            }
+        }
+
+        let mut inbox = Vec::new();
+        let mut gone = Vec::new();
+        for client in lobby.clients.iter_mut() {
+            match read_websocket::<LobbyRequest, _>(&mut client.socket) {
+                Some(requests) => inbox.extend(requests.into_iter().map(|r| (client.id, r))),
+                None => gone.push(client.id),
+            }
+        }
+
+        for (id, request) in inbox {
+            lobby.handle(id, request);
+        }
+        for id in gone {
+            lobby.leave(id);
+            lobby.clients.retain(|c| c.id != id);
+        }
+
+        lobby.reap_races();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,18 @@
+use serde::de::DeserializeOwned;
+
+// Tuning data lives in `assets/config`. Native builds read the file at startup so values can be
+// tweaked without recompiling; the web build (and missing files) fall back to the copy baked in at compile time.
+pub fn load<T: DeserializeOwned>(path: &str, embedded: &str) -> T {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let full_path = format!("assets/{}", path);
+        if let Ok(contents) = std::fs::read_to_string(&full_path) {
+            match serde_json::from_str(&contents) {
+                Ok(value) => return value,
+                Err(err) => bevy::log::warn!("Failed to parse {}: {}, using built-in defaults", full_path, err),
+            }
+        }
+    }
+
+    serde_json::from_str(embedded).unwrap_or_else(|err| panic!("Invalid built-in {}: {}", path, err))
+}
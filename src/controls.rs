@@ -0,0 +1,317 @@
+use bevy::prelude::*;
+use bevy::input::gamepad::{GamepadAxisChangedEvent, GamepadButtonChangedEvent};
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::mouse::{MouseButtonInput, MouseMotion};
+use bevy::input::touch::TouchInput;
+use crate::input::{key_name, InputBindings, MenuInput};
+use crate::save::SaveDir;
+
+// Picture of the device being used with what each control does, from the live bindings.
+// Opened from the pause menu and once on the very first launch.
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<ControlsHelp>()
+           .enable_state_scoped_entities::<ControlsHelp>()
+           .init_resource::<SaveDir>()
+           .init_resource::<ActiveDevice>()
+           .init_resource::<ControlsView>()
+           .add_systems(Startup, show_on_first_launch)
+           .add_systems(OnEnter(ControlsHelp::Shown), open_controls)
+           .add_systems(OnExit(ControlsHelp::Shown), remember_controls_seen)
+           .add_systems(Update, track_active_device)
+           .add_systems(Update, (
+               controls_input,
+               refresh_controls,
+           ).chain().run_if(in_state(ControlsHelp::Shown)));
+    }
+}
+
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ControlsHelp {
+    #[default]
+    Hidden,
+    Shown,
+}
+
+// Whatever the player touched last
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ActiveDevice {
+    #[default]
+    Keyboard,
+    MouseKeyboard,
+    Gamepad,
+    Touch,
+}
+
+impl ActiveDevice {
+    const ALL: [ActiveDevice; 4] = [ActiveDevice::Keyboard, ActiveDevice::MouseKeyboard, ActiveDevice::Gamepad, ActiveDevice::Touch];
+
+    fn image(self) -> &'static str {
+        match self {
+            ActiveDevice::Keyboard => "images/keyboard.png",
+            ActiveDevice::MouseKeyboard => "images/mousekeyboard.png",
+            ActiveDevice::Gamepad => "images/gamepad.png",
+            ActiveDevice::Touch => "images/mobile.png",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ActiveDevice::Keyboard => "Keyboard",
+            ActiveDevice::MouseKeyboard => "Mouse and keyboard",
+            ActiveDevice::Gamepad => "Gamepad",
+            ActiveDevice::Touch => "Touch screen",
+        }
+    }
+}
+
+// Device shown in the overlay, left/right browses the others
+#[derive(Resource, Default)]
+struct ControlsView {
+    device: ActiveDevice,
+}
+
+#[derive(Component)]
+struct ControlsRoot;
+
+const SEEN_KEY: &str = "controls_seen";
+const IMAGE_SIZE: f32 = 480.0;
+
+fn show_on_first_launch(mut next_help: ResMut<NextState<ControlsHelp>>, save_dir: Res<SaveDir>) {
+    if crate::save::read(&save_dir, SEEN_KEY).is_none() {
+        next_help.set(ControlsHelp::Shown);
+    }
+}
+
+fn remember_controls_seen(save_dir: Res<SaveDir>) {
+    crate::save::write(&save_dir, SEEN_KEY, "true");
+}
+
+fn track_active_device(
+    mut device: ResMut<ActiveDevice>,
+    mut key_events: EventReader<KeyboardInput>,
+    mut mouse_button_events: EventReader<MouseButtonInput>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut button_events: EventReader<GamepadButtonChangedEvent>,
+    mut axis_events: EventReader<GamepadAxisChangedEvent>,
+    mut touch_events: EventReader<TouchInput>,
+) {
+    let mut latest = *device;
+    // Keys alone don't turn mouse and keyboard back into just keyboard
+    if key_events.read().count() > 0 && latest != ActiveDevice::MouseKeyboard {
+        latest = ActiveDevice::Keyboard;
+    }
+    if mouse_button_events.read().count() > 0 || mouse_motion_events.read().any(|motion| motion.delta.length() > 2.0) {
+        latest = ActiveDevice::MouseKeyboard;
+    }
+    if button_events.read().count() > 0 || axis_events.read().any(|axis| axis.value.abs() > 0.5) {
+        latest = ActiveDevice::Gamepad;
+    }
+    if touch_events.read().count() > 0 {
+        latest = ActiveDevice::Touch;
+    }
+    if latest != *device {
+        *device = latest;
+    }
+}
+
+fn gamepad_button_name(button: GamepadButton) -> &'static str {
+    match button {
+        GamepadButton::South => "A",
+        GamepadButton::East => "B",
+        GamepadButton::West => "X",
+        GamepadButton::North => "Y",
+        GamepadButton::LeftTrigger => "LB",
+        GamepadButton::RightTrigger => "RB",
+        GamepadButton::LeftTrigger2 => "LT",
+        GamepadButton::RightTrigger2 => "RT",
+        GamepadButton::Select => "View",
+        GamepadButton::Start => "Menu",
+        GamepadButton::DPadUp | GamepadButton::DPadDown | GamepadButton::DPadLeft | GamepadButton::DPadRight => "D-pad",
+        _ => "?",
+    }
+}
+
+// Where each button sits on images/gamepad.png, as a fraction of its size
+fn gamepad_button_spot(button: GamepadButton) -> Option<Vec2> {
+    let spot = match button {
+        GamepadButton::South => Vec2::new(0.70, 0.43),
+        GamepadButton::East => Vec2::new(0.76, 0.37),
+        GamepadButton::West => Vec2::new(0.65, 0.37),
+        GamepadButton::North => Vec2::new(0.70, 0.32),
+        GamepadButton::LeftTrigger => Vec2::new(0.26, 0.24),
+        GamepadButton::RightTrigger => Vec2::new(0.70, 0.24),
+        GamepadButton::LeftTrigger2 => Vec2::new(0.26, 0.17),
+        GamepadButton::RightTrigger2 => Vec2::new(0.70, 0.17),
+        GamepadButton::Select => Vec2::new(0.40, 0.30),
+        GamepadButton::Start => Vec2::new(0.55, 0.30),
+        _ => return None,
+    };
+    Some(spot)
+}
+
+// (action, control) pairs for a device, straight from the bindings
+fn callouts(device: ActiveDevice, bindings: &InputBindings) -> Vec<(String, String)> {
+    let keys = |codes: &Vec<KeyCode>| codes.iter().map(|key| key_name(*key)).collect::<Vec<_>>().join(" / ");
+    let buttons = |buttons: &Vec<GamepadButton>| buttons.iter().map(|button| gamepad_button_name(*button)).collect::<Vec<_>>().join(" / ");
+
+    match device {
+        ActiveDevice::Keyboard | ActiveDevice::MouseKeyboard => {
+            let k = &bindings.keyboard_full;
+            let mut lines = vec![
+                ("Accelerate".to_string(), keys(&k.up)),
+                ("Brake".to_string(), keys(&k.down)),
+                ("Steer".to_string(), format!("{} / {}", keys(&k.left), keys(&k.right))),
+                ("Jump / drift".to_string(), keys(&k.jump)),
+                ("Use item".to_string(), keys(&k.use_item)),
+                ("Reset".to_string(), keys(&k.reset)),
+                ("Pause".to_string(), "Escape".to_string()),
+            ];
+            if device == ActiveDevice::MouseKeyboard {
+                lines.push(("Menus".to_string(), "Point and click".to_string()));
+            }
+            lines
+        }
+        ActiveDevice::Gamepad => {
+            let pad = &bindings.gamepad;
+            vec![
+                ("Accelerate".to_string(), buttons(&pad.accelerate)),
+                ("Brake".to_string(), buttons(&pad.brake)),
+                ("Steer".to_string(), "Left stick / D-pad".to_string()),
+                ("Jump / drift".to_string(), buttons(&pad.jump)),
+                ("Use item".to_string(), buttons(&pad.use_item)),
+                ("Reset".to_string(), buttons(&pad.reset)),
+                ("Pause".to_string(), "Menu".to_string()),
+            ]
+        }
+        ActiveDevice::Touch => vec![
+            ("Racing".to_string(), "Connect a keyboard or gamepad".to_string()),
+            ("Menus".to_string(), "Tap".to_string()),
+        ],
+    }
+}
+
+fn open_controls(mut view: ResMut<ControlsView>, device: Res<ActiveDevice>) {
+    view.device = *device;
+}
+
+fn controls_input(
+    menu: Res<MenuInput>,
+    mut view: ResMut<ControlsView>,
+    mut next_help: ResMut<NextState<ControlsHelp>>,
+) {
+    let all = ActiveDevice::ALL;
+    let index = all.iter().position(|device| *device == view.device).unwrap_or(0);
+    if menu.left {
+        view.device = all[(index + all.len() - 1) % all.len()];
+    }
+    if menu.right {
+        view.device = all[(index + 1) % all.len()];
+    }
+    if menu.confirm || menu.back {
+        next_help.set(ControlsHelp::Hidden);
+    }
+}
+
+// Rebuilt whenever the device shown or the bindings change
+fn refresh_controls(
+    mut commands: Commands,
+    view: Res<ControlsView>,
+    bindings: Res<InputBindings>,
+    asset_server: Res<AssetServer>,
+    root_query: Query<Entity, With<ControlsRoot>>,
+) {
+    if !view.is_changed() && !bindings.is_changed() && !root_query.is_empty() {
+        return;
+    }
+    for entity in root_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let device = view.device;
+    let font = asset_server.load("fonts/HK.ttf");
+    let text = |size: f32| TextFont { font: font.clone(), font_size: size, ..default() };
+    let lines = callouts(device, &bindings);
+
+    // Own camera, over the menus
+    let camera = commands.spawn((
+        Camera2d,
+        Camera { order: 60, clear_color: ClearColorConfig::None, ..default() },
+        ControlsRoot,
+        StateScoped(ControlsHelp::Shown),
+    )).id();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                column_gap: Val::Px(40.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.85)),
+            TargetCamera(camera),
+            ControlsRoot,
+            StateScoped(ControlsHelp::Shown),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Node {
+                    width: Val::Px(IMAGE_SIZE),
+                    height: Val::Px(IMAGE_SIZE),
+                    ..default()
+                },
+                ImageNode::new(asset_server.load(device.image())),
+            )).with_children(|image| {
+                // Gamepad buttons get their action written on the picture
+                if device != ActiveDevice::Gamepad {
+                    return;
+                }
+                let pad = &bindings.gamepad;
+                for (action, buttons) in [
+                    ("Accelerate", &pad.accelerate),
+                    ("Brake", &pad.brake),
+                    ("Jump / drift", &pad.jump),
+                    ("Use item", &pad.use_item),
+                    ("Reset", &pad.reset),
+                ] {
+                    for spot in buttons.iter().filter_map(|button| gamepad_button_spot(*button)) {
+                        image.spawn((
+                            Text::new(action),
+                            text(16.0),
+                            TextColor(Color::WHITE),
+                            BackgroundColor(Color::srgba(0.85, 0.15, 0.1, 0.85)),
+                            Node {
+                                position_type: PositionType::Absolute,
+                                left: Val::Percent(spot.x * 100.0),
+                                top: Val::Percent(spot.y * 100.0),
+                                padding: UiRect::axes(Val::Px(4.0), Val::Px(1.0)),
+                                ..default()
+                            },
+                        ));
+                    }
+                }
+            });
+
+            parent.spawn(Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(10.0),
+                ..default()
+            }).with_children(|column| {
+                column.spawn((Text::new(device.label()), text(48.0), TextColor(Color::srgb(1.0, 0.85, 0.0))));
+                for (action, control) in lines {
+                    column.spawn((Text::new(format!("{}:  {}", action, control)), text(28.0), TextColor(Color::WHITE)));
+                }
+                column.spawn((
+                    Text::new("Left/Right: other devices   Enter/Esc: close"),
+                    text(18.0),
+                    TextColor(Color::srgb(0.8, 0.8, 0.8)),
+                    Node { margin: UiRect::top(Val::Px(20.0)), ..default() },
+                ));
+            });
+        });
+}
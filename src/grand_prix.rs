@@ -0,0 +1,304 @@
+use bevy::prelude::*;
+use bevy::gltf::GltfAssetLabel;
+use serde::Deserialize;
+use crate::input::MenuInput;
+use crate::logic::{FinishOrder, GameMode, PlayerStats, RacePhase};
+use crate::menu::Screen;
+use crate::player::{KartCatalog, KartSelections, LocalPlayer};
+use crate::time_trial::format_time;
+use crate::track::LoadTrack;
+
+// A cup of races back to back: points for every finish, standings in between, a podium at the end
+pub struct GrandPrixPlugin;
+
+impl Plugin for GrandPrixPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(crate::config::load::<Cup>("config/grand_prix.json", include_str!("../assets/config/grand_prix.json")))
+           .init_resource::<GrandPrix>()
+           .add_systems(OnEnter(Screen::Select), start_grand_prix.run_if(is_grand_prix))
+           .add_systems(OnEnter(Screen::Race), load_cup_track.run_if(is_grand_prix))
+           .add_systems(OnEnter(Screen::Standings), spawn_standings)
+           .add_systems(OnEnter(Screen::Podium), spawn_podium)
+           .add_systems(Update, (
+               score_race.run_if(in_state(Screen::Race).and(is_grand_prix)),
+               standings_input.run_if(in_state(Screen::Standings)),
+               (podium_input, orbit_podium_camera).run_if(in_state(Screen::Podium)),
+           ));
+    }
+}
+
+// Tracks from `assets/tracks`, and points by finishing position
+#[derive(Resource, Deserialize)]
+pub struct Cup {
+    pub cup: String,
+    pub tracks: Vec<String>,
+    pub points: Vec<u32>,
+}
+
+#[derive(Resource, Default)]
+pub struct GrandPrix {
+    // Index into the cup's tracks
+    pub race: usize,
+    // Totals and what the last race gave, by local player index
+    pub points: Vec<u32>,
+    pub last_points: Vec<u32>,
+    // Last race time with penalties, None for a kart that didn't finish
+    pub last_times: Vec<Option<f32>>,
+    // Time since the last kart crossed the line
+    finished_for: f32,
+}
+
+impl GrandPrix {
+    // Local player indices, best first
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut players: Vec<usize> = (0..self.points.len()).collect();
+        players.sort_by_key(|player| std::cmp::Reverse(self.points[*player]));
+        players
+    }
+}
+
+// Let the finish sink in before the standings
+const STANDINGS_DELAY: f32 = 3.0;
+
+const PODIUM_SPOT: Vec3 = Vec3::new(0.0, 5000.0, 0.0);
+// Height and offset of the 1st, 2nd and 3rd place steps
+const PODIUM_STEPS: [(f32, f32); 3] = [(1.2, 0.0), (0.8, -1.6), (0.5, 1.6)];
+
+#[derive(Component)]
+struct PodiumCamera;
+
+fn is_grand_prix(mode: Res<GameMode>) -> bool {
+    *mode == GameMode::GrandPrix
+}
+
+fn start_grand_prix(mut grand_prix: ResMut<GrandPrix>) {
+    *grand_prix = GrandPrix::default();
+}
+
+fn load_cup_track(cup: Res<Cup>, grand_prix: Res<GrandPrix>, mut load_events: EventWriter<LoadTrack>) {
+    if let Some(track) = cup.tracks.get(grand_prix.race) {
+        info!("{} race {}: {}", cup.cup, grand_prix.race + 1, track);
+        load_events.send(LoadTrack(track.clone()));
+    }
+}
+
+// Once every local kart is home, hand out points in finishing order
+fn score_race(
+    kart_query: Query<(&LocalPlayer, &PlayerStats)>,
+    finish_order: Res<FinishOrder>,
+    cup: Res<Cup>,
+    mut grand_prix: ResMut<GrandPrix>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    time: Res<Time>,
+) {
+    if kart_query.is_empty() || kart_query.iter().any(|(_, stats)| !stats.finished) {
+        return;
+    }
+    grand_prix.finished_for += time.delta_secs();
+    if grand_prix.finished_for < STANDINGS_DELAY {
+        return;
+    }
+    grand_prix.finished_for = 0.0;
+
+    let players = kart_query.iter().map(|(local, _)| local.index + 1).max().unwrap_or(0);
+    grand_prix.points.resize(players, 0);
+    grand_prix.last_points = vec![0; players];
+    grand_prix.last_times = vec![None; players];
+    // The finish order already counts respawn penalties
+    for (place, kart) in finish_order.0.iter().enumerate() {
+        let Ok((local, stats)) = kart_query.get(*kart) else { continue; };
+        let points = cup.points.get(place).copied().unwrap_or(0);
+        grand_prix.points[local.index] += points;
+        grand_prix.last_points[local.index] = points;
+        grand_prix.last_times[local.index] = stats.finish_time;
+    }
+
+    if grand_prix.race + 1 < cup.tracks.len() {
+        next_screen.set(Screen::Standings);
+    } else {
+        info!("{} over: {:?}", cup.cup, grand_prix.points);
+        next_screen.set(Screen::Podium);
+    }
+}
+
+fn player_name(index: usize, selections: &KartSelections) -> String {
+    match selections.0.get(index) {
+        Some(build) => format!("Player {} ({})", index + 1, build.character),
+        None => format!("Player {}", index + 1),
+    }
+}
+
+fn spawn_standings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    cup: Res<Cup>,
+    grand_prix: Res<GrandPrix>,
+    selections: Res<KartSelections>,
+) {
+    let font = asset_server.load("fonts/HK.ttf");
+    let text = |size: f32| TextFont { font: font.clone(), font_size: size, ..default() };
+    let next_track = cup.tracks.get(grand_prix.race + 1).cloned().unwrap_or_default();
+
+    let camera = commands.spawn((
+        Camera2d,
+        Camera { order: 50, clear_color: ClearColorConfig::None, ..default() },
+        StateScoped(Screen::Standings),
+    )).id();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(14.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
+            TargetCamera(camera),
+            StateScoped(Screen::Standings),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(format!("{} - after race {} of {}", cup.cup, grand_prix.race + 1, cup.tracks.len())),
+                text(56.0),
+                TextColor(Color::srgb(1.0, 0.85, 0.0)),
+            ));
+            for (rank, player) in grand_prix.ranking().into_iter().enumerate() {
+                parent.spawn((
+                    Text::new(format!(
+                        "{}. {}   {} pts (+{})   {}",
+                        rank + 1,
+                        player_name(player, &selections),
+                        grand_prix.points[player],
+                        grand_prix.last_points.get(player).copied().unwrap_or(0),
+                        grand_prix.last_times.get(player).copied().flatten().map_or(String::new(), format_time),
+                    )),
+                    text(36.0),
+                    TextColor(Color::WHITE),
+                ));
+            }
+            parent.spawn((
+                Text::new(format!("Enter: next race - {}", next_track)),
+                text(24.0),
+                TextColor(Color::srgb(0.8, 0.8, 0.8)),
+                Node { margin: UiRect::top(Val::Px(30.0)), ..default() },
+            ));
+        });
+}
+
+fn standings_input(
+    menu: Res<MenuInput>,
+    mut grand_prix: ResMut<GrandPrix>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+) {
+    if menu.confirm {
+        grand_prix.race += 1;
+        next_screen.set(Screen::Race);
+        next_phase.set(RacePhase::Countdown);
+    }
+}
+
+// Top three on the steps, far away from the track like the select screen's turntable
+fn spawn_podium(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    cup: Res<Cup>,
+    grand_prix: Res<GrandPrix>,
+    selections: Res<KartSelections>,
+    catalog: Res<KartCatalog>,
+) {
+    let colors = [Color::srgb(1.0, 0.84, 0.0), Color::srgb(0.75, 0.75, 0.78), Color::srgb(0.8, 0.5, 0.2)];
+    let ranking = grand_prix.ranking();
+
+    for (place, (height, offset)) in PODIUM_STEPS.iter().enumerate() {
+        let step_pos = PODIUM_SPOT + Vec3::new(*offset, height / 2.0, 0.0);
+        commands.spawn((
+            Mesh3d(meshes.add(Cuboid::new(1.5, *height, 1.5))),
+            MeshMaterial3d(materials.add(StandardMaterial { base_color: colors[place], ..default() })),
+            Transform::from_translation(step_pos),
+            StateScoped(Screen::Podium),
+        ));
+
+        let Some(player) = ranking.get(place) else { continue; };
+        let build = selections.0.get(*player).cloned().unwrap_or_default();
+        commands.spawn((
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(catalog.model(&build).to_string()))),
+            // Facing the camera
+            Transform::from_translation(PODIUM_SPOT + Vec3::new(*offset, *height, 0.0)).with_scale(Vec3::splat(0.01)),
+            StateScoped(Screen::Podium),
+        ));
+    }
+
+    commands.spawn((
+        PointLight { intensity: 2_000_000.0, range: 30.0, shadows_enabled: true, ..default() },
+        Transform::from_translation(PODIUM_SPOT + Vec3::new(0.0, 6.0, 4.0)),
+        StateScoped(Screen::Podium),
+    ));
+
+    let camera = commands.spawn((
+        Camera3d::default(),
+        Camera { order: 100, ..default() },
+        Transform::from_translation(PODIUM_SPOT + Vec3::new(0.0, 3.0, 7.0)).looking_at(PODIUM_SPOT + Vec3::Y, Vec3::Y),
+        PodiumCamera,
+        StateScoped(Screen::Podium),
+    )).id();
+
+    let font = asset_server.load("fonts/HK.ttf");
+    let lines: Vec<String> = ranking.iter().enumerate()
+        .map(|(rank, player)| format!("{}. {}   {} pts", rank + 1, player_name(*player, &selections), grand_prix.points[*player]))
+        .collect();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                padding: UiRect::all(Val::Px(30.0)),
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            TargetCamera(camera),
+            StateScoped(Screen::Podium),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(format!("{} results", cup.cup)),
+                TextFont { font: font.clone(), font_size: 64.0, ..default() },
+                TextColor(Color::srgb(1.0, 0.85, 0.0)),
+            ));
+            for line in lines {
+                parent.spawn((
+                    Text::new(line),
+                    TextFont { font: font.clone(), font_size: 32.0, ..default() },
+                    TextColor(Color::WHITE),
+                ));
+            }
+            parent.spawn((
+                Text::new("Enter: back to the main menu"),
+                TextFont { font: font.clone(), font_size: 22.0, ..default() },
+                TextColor(Color::srgb(0.8, 0.8, 0.8)),
+            ));
+        });
+}
+
+fn orbit_podium_camera(mut camera_query: Query<&mut Transform, With<PodiumCamera>>, time: Res<Time>) {
+    let angle = (time.elapsed_secs() * 0.3).sin() * 0.6;
+    for mut transform in camera_query.iter_mut() {
+        let eye = PODIUM_SPOT + Quat::from_rotation_y(angle) * Vec3::new(0.0, 3.0, 7.0);
+        *transform = Transform::from_translation(eye).looking_at(PODIUM_SPOT + Vec3::Y, Vec3::Y);
+    }
+}
+
+fn podium_input(menu: Res<MenuInput>, mut next_screen: ResMut<NextState<Screen>>) {
+    if menu.confirm {
+        next_screen.set(Screen::MainMenu);
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy::core::FrameCount;
+use bevy::log::LogPlugin;
+use bevy::scene::ScenePlugin;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy_rapier3d::prelude::*;
+use crate::input::KartInput;
+use crate::items::ItemsPlugin;
+use crate::logic::{LapCompleted, LogicPlugin, PlayerStats, RaceConfig, RaceFinished};
+use crate::player::{spawn_kart, LocalPlayers, PlayerPlugin};
+use crate::save::SaveDir;
+use crate::track::FlatTrackPlugin;
+
+// Every update advances the simulation by exactly this much, whatever the wall clock says
+pub const HEADLESS_TIMESTEP: f64 = 1.0 / 60.0;
+
+// Just enough of Bevy to run the game logic and physics: no window, renderer, audio or winit.
+// GLB scenes (kart and item models) have no loader here and simply stay empty.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+               MinimalPlugins,
+               TransformPlugin,
+               HierarchyPlugin,
+               AssetPlugin::default(),
+               ScenePlugin,
+               StatesPlugin,
+               bevy::input::InputPlugin,
+           ))
+           .init_asset::<Mesh>()
+           .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(HEADLESS_TIMESTEP)))
+           .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+           .add_systems(PreUpdate, apply_scripted_input.after(crate::input::gather_input));
+    }
+}
+
+// Inputs played back one per frame, the last one is held once the script runs out
+#[derive(Component)]
+pub struct ScriptedInput {
+    pub inputs: Vec<KartInput>,
+    pub frame: usize,
+}
+
+impl ScriptedInput {
+    pub fn new(inputs: Vec<KartInput>) -> Self {
+        Self { inputs, frame: 0 }
+    }
+
+    // Same input every frame
+    pub fn hold(input: KartInput) -> Self {
+        Self::new(vec![input])
+    }
+}
+
+fn apply_scripted_input(mut query: Query<(&mut ScriptedInput, &mut KartInput)>) {
+    for (mut script, mut input) in query.iter_mut() {
+        let Some(last) = script.inputs.len().checked_sub(1) else { continue; };
+        *input = script.inputs[script.frame.min(last)];
+        script.frame += 1;
+    }
+}
+
+// Every headless app saves into its own empty directory, never the player's saves
+fn temp_save_dir() -> SaveDir {
+    static APPS: AtomicUsize = AtomicUsize::new(0);
+    let app = APPS.fetch_add(1, Ordering::Relaxed);
+    SaveDir(std::env::temp_dir().join(format!("mariok-bevy-{}-{}", std::process::id(), app)))
+}
+
+// The race simulation on the flat track with no local players; callers spawn karts with `spawn_kart`
+pub fn headless_app(race: RaceConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins(HeadlessPlugin)
+       .insert_resource(temp_save_dir())
+       .insert_resource(LocalPlayers(0))
+       .insert_resource(race)
+       .add_plugins((crate::input::InputPlugin, FlatTrackPlugin, PlayerPlugin, LogicPlugin, ItemsPlugin));
+    app
+}
+
+#[derive(Resource)]
+struct FrameLimit(u32);
+
+// `--headless`: one scripted kart holding the throttle for `frames` frames, logging its race
+pub fn run(race: RaceConfig, frames: u32) {
+    let mut app = headless_app(race);
+    app.add_plugins(LogPlugin::default())
+       .insert_resource(FrameLimit(frames))
+       .add_systems(Startup, spawn_scripted_kart)
+       .add_systems(Update, (log_race_events, stop_after_frames))
+       .run();
+}
+
+fn spawn_scripted_kart(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let kart = spawn_kart(&mut commands, &asset_server, Vec3::new(0.0, 1.0, 0.0), Quat::IDENTITY);
+    commands.entity(kart).insert(ScriptedInput::hold(KartInput { throttle: 1.0, ..default() }));
+}
+
+fn log_race_events(
+    mut lap_events: EventReader<LapCompleted>,
+    mut finish_events: EventReader<RaceFinished>,
+    stats_query: Query<&PlayerStats>,
+) {
+    for event in lap_events.read() {
+        info!("{:?} completed lap {}", event.kart, event.lap);
+    }
+    for event in finish_events.read() {
+        let coins = stats_query.get(event.kart).map(|s| s.coin_count).unwrap_or(0);
+        info!("{:?} finished in position {} with {} coins", event.kart, event.position, coins);
+    }
+}
+
+fn stop_after_frames(frame: Res<FrameCount>, limit: Res<FrameLimit>, mut exit: EventWriter<AppExit>) {
+    if frame.0 >= limit.0 {
+        info!("Stopping after {} frames", frame.0);
+        exit.send(AppExit::Success);
+    }
+}
@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputBindings>()
+           .init_resource::<MenuInput>()
+           .add_systems(PreUpdate, (gather_input, gather_menu_input).after(bevy::input::InputSystem));
+    }
+}
+
+// What a kart wants to do this frame, whoever (keyboard, gamepad, network...) is driving it
+#[derive(Component, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct KartInput {
+    pub throttle: f32,
+    pub steer: f32,
+    pub jump: bool,
+    pub use_item: bool,
+    pub reset: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputDevice {
+    KeyboardFull,
+    KeyboardLeft,
+    KeyboardRight,
+    // Nth connected gamepad
+    Gamepad(usize),
+}
+
+// Devices driving a local kart, merged together
+#[derive(Component)]
+pub struct PlayerController {
+    pub devices: Vec<InputDevice>,
+}
+
+impl PlayerController {
+    // Keyboard halves go to the first two players, gamepads to whoever is left
+    pub fn for_player(index: usize, player_count: usize) -> Self {
+        let devices = match (player_count, index) {
+            (1, _) => vec![InputDevice::KeyboardFull, InputDevice::Gamepad(0)],
+            (2, 0) => vec![InputDevice::KeyboardLeft, InputDevice::Gamepad(0)],
+            (2, _) => vec![InputDevice::KeyboardRight, InputDevice::Gamepad(1)],
+            (_, 0) => vec![InputDevice::KeyboardLeft],
+            (_, 1) => vec![InputDevice::KeyboardRight],
+            (_, i) => vec![InputDevice::Gamepad(i - 2)],
+        };
+        Self { devices }
+    }
+}
+
+pub struct KeyBindings {
+    pub up: Vec<KeyCode>,
+    pub down: Vec<KeyCode>,
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub jump: Vec<KeyCode>,
+    pub use_item: Vec<KeyCode>,
+    pub reset: Vec<KeyCode>,
+}
+
+pub struct GamepadBindings {
+    pub accelerate: Vec<GamepadButton>,
+    pub brake: Vec<GamepadButton>,
+    pub jump: Vec<GamepadButton>,
+    pub use_item: Vec<GamepadButton>,
+    pub reset: Vec<GamepadButton>,
+}
+
+#[derive(Resource)]
+pub struct InputBindings {
+    pub keyboard_full: KeyBindings,
+    pub keyboard_left: KeyBindings,
+    pub keyboard_right: KeyBindings,
+    pub gamepad: GamepadBindings,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self {
+            // Solo: both QWERTY/AZERTY layouts and the arrows
+            keyboard_full: KeyBindings {
+                up: vec![KeyCode::ArrowUp, KeyCode::KeyW, KeyCode::KeyZ],
+                down: vec![KeyCode::ArrowDown, KeyCode::KeyS],
+                left: vec![KeyCode::ArrowLeft, KeyCode::KeyA, KeyCode::KeyQ],
+                right: vec![KeyCode::ArrowRight, KeyCode::KeyD],
+                jump: vec![KeyCode::KeyV, KeyCode::Space],
+                use_item: vec![KeyCode::KeyE, KeyCode::ShiftRight],
+                reset: vec![KeyCode::KeyR],
+            },
+            keyboard_left: KeyBindings {
+                up: vec![KeyCode::KeyW, KeyCode::KeyZ],
+                down: vec![KeyCode::KeyS],
+                left: vec![KeyCode::KeyA, KeyCode::KeyQ],
+                right: vec![KeyCode::KeyD],
+                jump: vec![KeyCode::KeyV, KeyCode::Space],
+                use_item: vec![KeyCode::KeyE],
+                reset: vec![KeyCode::KeyR],
+            },
+            keyboard_right: KeyBindings {
+                up: vec![KeyCode::ArrowUp],
+                down: vec![KeyCode::ArrowDown],
+                left: vec![KeyCode::ArrowLeft],
+                right: vec![KeyCode::ArrowRight],
+                jump: vec![KeyCode::ControlRight, KeyCode::Numpad0],
+                use_item: vec![KeyCode::ShiftRight, KeyCode::Numpad1],
+                reset: vec![KeyCode::Backspace],
+            },
+            gamepad: GamepadBindings {
+                accelerate: vec![GamepadButton::South, GamepadButton::RightTrigger2],
+                brake: vec![GamepadButton::West, GamepadButton::LeftTrigger2],
+                jump: vec![GamepadButton::RightTrigger, GamepadButton::LeftTrigger],
+                use_item: vec![GamepadButton::East],
+                reset: vec![GamepadButton::Select],
+            },
+        }
+    }
+}
+
+impl InputBindings {
+    pub fn keys(&self, device: InputDevice) -> Option<&KeyBindings> {
+        match device {
+            InputDevice::KeyboardFull => Some(&self.keyboard_full),
+            InputDevice::KeyboardLeft => Some(&self.keyboard_left),
+            InputDevice::KeyboardRight => Some(&self.keyboard_right),
+            InputDevice::Gamepad(_) => None,
+        }
+    }
+}
+
+pub fn gather_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<(Entity, &Gamepad)>,
+    bindings: Res<InputBindings>,
+    mut query: Query<(&PlayerController, &mut KartInput)>,
+) {
+    // Stable ordering so "gamepad 0" doesn't jump around between frames
+    let mut pads: Vec<(Entity, &Gamepad)> = gamepads.iter().collect();
+    pads.sort_by_key(|(entity, _)| *entity);
+
+    for (controller, mut input) in query.iter_mut() {
+        let mut merged = KartInput::default();
+
+        for device in &controller.devices {
+            if let Some(keys) = bindings.keys(*device) {
+                let pressed = |codes: &Vec<KeyCode>| keyboard.any_pressed(codes.iter().copied());
+                if pressed(&keys.up) { merged.throttle += 1.0; }
+                if pressed(&keys.down) { merged.throttle -= 1.0; }
+                if pressed(&keys.left) { merged.steer += 1.0; }
+                if pressed(&keys.right) { merged.steer -= 1.0; }
+                merged.jump |= pressed(&keys.jump);
+                merged.use_item |= keyboard.any_just_pressed(keys.use_item.iter().copied());
+                merged.reset |= keyboard.any_just_pressed(keys.reset.iter().copied());
+            } else if let InputDevice::Gamepad(n) = device {
+                let Some((_, gamepad)) = pads.get(*n) else { continue; };
+                let pad = &bindings.gamepad;
+                if gamepad.any_pressed(pad.accelerate.iter().copied()) { merged.throttle += 1.0; }
+                if gamepad.any_pressed(pad.brake.iter().copied()) { merged.throttle -= 1.0; }
+                let stick = gamepad.left_stick().x + gamepad.dpad().x;
+                merged.steer -= stick;
+                merged.jump |= gamepad.any_pressed(pad.jump.iter().copied());
+                merged.use_item |= gamepad.any_just_pressed(pad.use_item.iter().copied());
+                merged.reset |= gamepad.any_just_pressed(pad.reset.iter().copied());
+            }
+        }
+
+        merged.throttle = merged.throttle.clamp(-1.0, 1.0);
+        merged.steer = merged.steer.clamp(-1.0, 1.0);
+        *input = merged;
+    }
+}
+
+// "KeyW" -> "W", "ArrowUp" -> "Up", for showing bindings to players
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+    for prefix in ["Key", "Digit", "Arrow"] {
+        if let Some(rest) = name.strip_prefix(prefix) {
+            return rest.to_string();
+        }
+    }
+    name
+}
+
+// Menu navigation for the screens around the race: any keyboard or gamepad, one step per press
+#[derive(Resource, Default)]
+pub struct MenuInput {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub confirm: bool,
+    pub back: bool,
+}
+
+pub fn gather_menu_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut menu: ResMut<MenuInput>,
+) {
+    let key = |codes: &[KeyCode]| keyboard.any_just_pressed(codes.iter().copied());
+    let mut next = MenuInput {
+        up: key(&[KeyCode::ArrowUp, KeyCode::KeyW, KeyCode::KeyZ]),
+        down: key(&[KeyCode::ArrowDown, KeyCode::KeyS]),
+        left: key(&[KeyCode::ArrowLeft, KeyCode::KeyA, KeyCode::KeyQ]),
+        right: key(&[KeyCode::ArrowRight, KeyCode::KeyD]),
+        confirm: key(&[KeyCode::Enter, KeyCode::NumpadEnter, KeyCode::Space]),
+        back: key(&[KeyCode::Escape, KeyCode::Backspace]),
+    };
+
+    for gamepad in gamepads.iter() {
+        next.up |= gamepad.just_pressed(GamepadButton::DPadUp);
+        next.down |= gamepad.just_pressed(GamepadButton::DPadDown);
+        next.left |= gamepad.just_pressed(GamepadButton::DPadLeft);
+        next.right |= gamepad.just_pressed(GamepadButton::DPadRight);
+        next.confirm |= gamepad.just_pressed(GamepadButton::South);
+        next.back |= gamepad.just_pressed(GamepadButton::East);
+    }
+
+    *menu = next;
+}
@@ -1,91 +1,264 @@
 use bevy::prelude::*;
 use bevy::gltf::GltfAssetLabel;
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 use crate::player::Kart;
-use crate::logic::PlayerStats;
+use crate::input::KartInput;
+use crate::logic::{PlayerStats, RacePosition};
+use crate::track::{is_kill_volume, KillVolume, TrackDefinition, TrackEntity};
 
 pub struct ItemsPlugin;
 
 impl Plugin for ItemsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_gameplay_objects)
-           .add_systems(Update, (handle_item_collision, handle_coin_collision, animate_objects));
+        app.insert_resource(crate::config::load::<ItemOdds>("config/item_odds.json", include_str!("../assets/config/item_odds.json")))
+           .insert_resource(Roulette(0x2545_f491_4f6c_dd1d))
+           .add_event::<KartHit>()
+           .add_systems(Update, (
+               spawn_gameplay_objects.run_if(resource_exists_and_changed::<TrackDefinition>),
+               (
+                   handle_item_collision,
+                   respawn_item_boxes,
+                   handle_coin_collision,
+                   use_item.run_if(in_state(crate::logic::RacePhase::Racing)),
+                   move_shells,
+                   handle_hazard_collision,
+                   apply_kart_hits,
+                   despawn_lost_items,
+               ).in_set(ItemRules),
+               animate_objects,
+           ));
     }
 }
 
+// Who gets which box, coin and hit. Online clients leave it to the server and mirror its snapshots.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ItemRules;
+
 #[derive(Component)]
 pub struct ItemBox;
 
+// Broken box waiting to come back, seconds left
+#[derive(Component)]
+pub struct BrokenItemBox(pub f32);
+
 #[derive(Component)]
 pub struct Coin;
 
+// Place of a box or coin in the track definition, the same on every machine
+#[derive(Component)]
+pub struct LayoutIndex(pub usize);
+
 #[derive(Component)]
 struct Rotating;
 
-fn spawn_gameplay_objects(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // Porting positions from a typical Mario Kart layout or original project observation
-    let box_positions = vec![
-        Vec3::new(5.0, 1.0, 5.0),
-        Vec3::new(5.0, 1.0, -5.0),
-        Vec3::new(-5.0, 1.0, 5.0),
-    ];
-
-    let coin_positions = vec![
-        Vec3::new(3.0, 1.0, 0.0),
-        Vec3::new(-3.0, 1.0, 0.0),
-        Vec3::new(0.0, 1.0, 3.0),
-        Vec3::new(0.0, 1.0, -3.0),
-    ];
-
-    // Spawn Item Boxes
-    for pos in box_positions {
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Item {
+    Banana,
+    Coin,
+    Mushroom,
+    TripleMushroom,
+    GreenShell,
+    RedShell,
+    Star,
+}
+
+impl Item {
+    // Model of the item once it's out on the track
+    pub fn model(self) -> &'static str {
+        match self {
+            Item::Banana => "models/items/banana_peel_mario_kart.glb",
+            Item::Coin => "models/misc/super_mario_bros_coin.glb",
+            _ => "models/items/mario_shell_red.glb",
+        }
+    }
+
+    pub fn uses(self) -> u8 {
+        match self {
+            Item::TripleMushroom => 3,
+            _ => 1,
+        }
+    }
+
+    // HUD image and tint
+    pub fn icon(self) -> (&'static str, Color) {
+        match self {
+            Item::Banana => ("images/banana.webp", Color::WHITE),
+            Item::Coin => ("particles/circle_coin.png", Color::WHITE),
+            Item::Mushroom | Item::TripleMushroom => ("images/mushroom.png", Color::WHITE),
+            Item::GreenShell => ("images/shell.webp", Color::srgb(0.3, 1.0, 0.3)),
+            Item::RedShell => ("images/shell.webp", Color::WHITE),
+            Item::Star => ("particles/circle.png", Color::srgb(1.0, 0.9, 0.1)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ItemChance {
+    pub item: Item,
+    pub weight: f32,
+}
+
+// Roulette table, one row per race position (1st first). Positions past the last row use the last row.
+#[derive(Resource, Deserialize)]
+pub struct ItemOdds {
+    pub positions: Vec<Vec<ItemChance>>,
+}
+
+impl ItemOdds {
+    // `roll` is uniform in [0, 1)
+    pub fn pick(&self, position: usize, roll: f32) -> Option<Item> {
+        let row = self.positions.get(position.saturating_sub(1).min(self.positions.len().saturating_sub(1)))?;
+        let total: f32 = row.iter().map(|chance| chance.weight).sum();
+        let mut target = roll * total;
+        for chance in row {
+            if target < chance.weight {
+                return Some(chance.item);
+            }
+            target -= chance.weight;
+        }
+        row.last().map(|chance| chance.item)
+    }
+}
+
+// Small xorshift generator so rolls don't need an external RNG crate
+#[derive(Resource)]
+pub struct Roulette(pub u64);
+
+impl Roulette {
+    pub fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[derive(Component, Default)]
+pub struct HeldItem {
+    pub item: Option<Item>,
+    pub uses: u8,
+}
+
+#[derive(Component)]
+pub struct Banana;
+
+#[derive(Component)]
+pub struct Shell {
+    pub owner: Entity,
+    pub velocity: Vec3,
+    pub target: Option<Entity>,
+    pub lifetime: f32,
+}
+
+#[derive(Event)]
+pub struct KartHit {
+    pub kart: Entity,
+}
+
+// Knocked out of a battle round: no more boxes or items until the next one
+#[derive(Component)]
+pub struct OutOfRound;
+
+// Item boxes and coins laid out by the track definition
+fn spawn_gameplay_objects(mut commands: Commands, asset_server: Res<AssetServer>, track: Res<TrackDefinition>) {
+    // Spawn Item Boxes, models as children so their scale doesn't shrink the colliders
+    for (index, pos) in track.item_boxes.iter().enumerate() {
         commands.spawn((
-            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/misc/mario_kart_item_box.glb"))),
-            Transform::from_translation(pos).with_scale(Vec3::splat(0.01)),
+            Transform::from_translation(Vec3::from(*pos)),
+            Visibility::default(),
             Collider::cuboid(0.8, 0.8, 0.8),
             Sensor,
             ItemBox,
+            LayoutIndex(index),
             Rotating,
-        ));
+            TrackEntity,
+        )).with_children(|parent| {
+            parent.spawn((
+                SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/misc/mario_kart_item_box.glb"))),
+                Transform::from_scale(Vec3::splat(0.01)),
+            ));
+        });
     }
 
-    // Spawn Coins
-    for pos in coin_positions {
-        commands.spawn((
-            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/misc/super_mario_bros_coin.glb"))),
-            Transform::from_translation(pos).with_scale(Vec3::splat(0.01)),
-            Collider::ball(0.5),
-            Sensor,
-            Coin,
-            Rotating,
-        ));
+    for (index, pos) in track.coins.iter().enumerate() {
+        let coin = spawn_coin(&mut commands, &asset_server, Vec3::from(*pos));
+        commands.entity(coin).insert(LayoutIndex(index));
     }
 }
 
+// Part of the track, gone with it
+pub fn spawn_coin(commands: &mut Commands, asset_server: &AssetServer, pos: Vec3) -> Entity {
+    commands.spawn((
+        Transform::from_translation(pos),
+        Visibility::default(),
+        Collider::ball(0.5),
+        Sensor,
+        Coin,
+        Rotating,
+        TrackEntity,
+    )).with_children(|parent| {
+        parent.spawn((
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(Item::Coin.model()))),
+            Transform::from_scale(Vec3::splat(0.01)),
+        ));
+    }).id()
+}
+
 fn handle_item_collision(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
     item_query: Query<Entity, With<ItemBox>>,
-    mut player_query: Query<&mut Kart>,
+    mut player_query: Query<(&mut HeldItem, &RacePosition), Without<OutOfRound>>,
+    odds: Res<ItemOdds>,
+    mut roulette: ResMut<Roulette>,
+    track: Res<TrackDefinition>,
+    time: Res<Time>,
 ) {
     for event in collision_events.read() {
         if let CollisionEvent::Started(e1, e2, _) = event {
-            let (item_ent, _) = if item_query.contains(*e1) { (*e1, *e2) } 
-                                        else if item_query.contains(*e2) { (*e2, *e1) } 
-                                        else { continue; };
+            let (item_ent, kart_ent) = if item_query.contains(*e1) { (*e1, *e2) }
+                                       else if item_query.contains(*e2) { (*e2, *e1) }
+                                       else { continue; };
 
-            if let Ok(mut kart) = player_query.get_single_mut() {
-                // Random item logic (simplified)
-                kart.is_boosting = true;
-                kart.boost_timer = 2.0;
-                
-                commands.entity(item_ent).despawn_recursive();
-                info!("Item collected! Boost activated!");
+            if let Ok((mut held, position)) = player_query.get_mut(kart_ent) {
+                // A kart already holding something just breaks the box
+                if held.item.is_none() {
+                    roulette.0 ^= time.elapsed_secs_f64().to_bits();
+                    if let Some(item) = odds.pick(position.0, roulette.next_f32()) {
+                        held.item = Some(item);
+                        held.uses = item.uses();
+                        info!("Item collected in position {}: {:?}", position.0, item);
+                    }
+                }
+
+                // Tracks that respawn boxes keep the broken one around, hidden
+                match track.item_box_respawn {
+                    Some(delay) => {
+                        commands.entity(item_ent).insert((BrokenItemBox(delay), ColliderDisabled, Visibility::Hidden));
+                    }
+                    None => commands.entity(item_ent).despawn_recursive(),
+                }
             }
         }
     }
 }
 
+fn respawn_item_boxes(
+    mut commands: Commands,
+    mut box_query: Query<(Entity, &mut BrokenItemBox)>,
+    time: Res<Time>,
+) {
+    for (entity, mut broken) in box_query.iter_mut() {
+        broken.0 -= time.delta_secs();
+        if broken.0 <= 0.0 {
+            commands.entity(entity)
+                .remove::<(BrokenItemBox, ColliderDisabled)>()
+                .insert(Visibility::Inherited);
+        }
+    }
+}
+
 fn handle_coin_collision(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
@@ -94,11 +267,11 @@ fn handle_coin_collision(
 ) {
     for event in collision_events.read() {
         if let CollisionEvent::Started(e1, e2, _) = event {
-            let (coin_ent, _) = if coin_query.contains(*e1) { (*e1, *e2) } 
-                                        else if coin_query.contains(*e2) { (*e2, *e1) } 
-                                        else { continue; };
+            let (coin_ent, kart_ent) = if coin_query.contains(*e1) { (*e1, *e2) }
+                                       else if coin_query.contains(*e2) { (*e2, *e1) }
+                                       else { continue; };
 
-            if let Ok(mut stats) = player_query.get_single_mut() {
+            if let Ok(mut stats) = player_query.get_mut(kart_ent) {
                 stats.coin_count += 1;
                 commands.entity(coin_ent).despawn_recursive();
                 info!("Coin collected! Total: {}", stats.coin_count);
@@ -107,6 +280,176 @@ fn handle_coin_collision(
     }
 }
 
+fn use_item(
+    mut commands: Commands,
+    mut kart_query: Query<(Entity, &Transform, &mut Kart, &mut HeldItem, &mut PlayerStats, &RacePosition, &KartInput), Without<OutOfRound>>,
+    asset_server: Res<AssetServer>,
+) {
+    // Red shells lock onto whoever is one place ahead
+    let positions: Vec<(Entity, usize)> = kart_query.iter().map(|(e, _, _, _, _, p, _)| (e, p.0)).collect();
+
+    for (entity, transform, mut kart, mut held, mut stats, position, input) in kart_query.iter_mut() {
+        if !input.use_item {
+            continue;
+        }
+        let Some(item) = held.item else { continue; };
+
+        match item {
+            Item::Mushroom | Item::TripleMushroom => {
+                kart.is_boosting = true;
+                kart.boost_timer = 1.5;
+            }
+            Item::Star => {
+                kart.is_boosting = true;
+                kart.boost_timer = 6.0;
+                kart.star_timer = 6.0;
+            }
+            Item::Coin => {
+                stats.coin_count += 2;
+            }
+            Item::Banana => {
+                // Model as a child so its scale doesn't shrink the collider
+                commands.spawn((
+                    Transform::from_translation(transform.translation + *transform.back() * 1.5),
+                    Visibility::default(),
+                    Collider::ball(0.4),
+                    Sensor,
+                    Banana,
+                    TrackEntity,
+                )).with_children(|parent| {
+                    parent.spawn((
+                        SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(Item::Banana.model()))),
+                        Transform::from_scale(Vec3::splat(0.01)),
+                    ));
+                });
+            }
+            Item::GreenShell | Item::RedShell => {
+                let target = if item == Item::RedShell && position.0 > 1 {
+                    positions.iter().find(|(_, p)| *p == position.0 - 1).map(|(e, _)| *e)
+                } else {
+                    None
+                };
+                commands.spawn((
+                    Transform::from_translation(transform.translation + *transform.forward() * 1.5),
+                    Visibility::default(),
+                    Collider::ball(0.4),
+                    Sensor,
+                    Shell {
+                        owner: entity,
+                        velocity: *transform.forward() * 45.0,
+                        target,
+                        lifetime: 6.0,
+                    },
+                    TrackEntity,
+                )).with_children(|parent| {
+                    parent.spawn((
+                        SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(item.model()))),
+                        Transform::from_scale(Vec3::splat(0.01)),
+                    ));
+                });
+            }
+        }
+
+        held.uses = held.uses.saturating_sub(1);
+        if held.uses == 0 {
+            held.item = None;
+        }
+    }
+}
+
+fn move_shells(
+    mut commands: Commands,
+    mut shell_query: Query<(Entity, &mut Transform, &mut Shell), Without<Kart>>,
+    kart_query: Query<&Transform, With<Kart>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut transform, mut shell) in shell_query.iter_mut() {
+        shell.lifetime -= dt;
+        if shell.lifetime <= 0.0 {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        // Homing: turn the velocity towards the target, keeping the speed
+        if let Some(target) = shell.target.and_then(|t| kart_query.get(t).ok()) {
+            let speed = shell.velocity.length();
+            let desired = (target.translation - transform.translation).normalize_or_zero() * speed;
+            shell.velocity = shell.velocity.lerp(desired, (4.0 * dt).min(1.0)).normalize_or_zero() * speed;
+        }
+
+        transform.translation += shell.velocity * dt;
+        transform.rotate_y(10.0 * dt);
+    }
+}
+
+// Bananas and shells that end up in water or off the map are gone
+fn despawn_lost_items(
+    mut commands: Commands,
+    item_query: Query<(Entity, &Transform), Or<(With<Banana>, With<Shell>)>>,
+    kill_query: Query<(), With<KillVolume>>,
+    parent_query: Query<&Parent>,
+    track: Res<TrackDefinition>,
+    rapier: ReadDefaultRapierContext,
+) {
+    let rapier = rapier.single();
+    for (entity, transform) in item_query.iter() {
+        let mut lost = transform.translation.y < track.fall_height;
+        rapier.intersections_with_point(transform.translation, QueryFilter::default().exclude_collider(entity), |hit| {
+            lost |= is_kill_volume(hit, &kill_query, &parent_query);
+            !lost
+        });
+        if lost {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn handle_hazard_collision(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    banana_query: Query<(), With<Banana>>,
+    shell_query: Query<&Shell>,
+    kart_query: Query<(), With<Kart>>,
+    mut hit_events: EventWriter<KartHit>,
+) {
+    for event in collision_events.read() {
+        if let CollisionEvent::Started(e1, e2, _) = event {
+            let (hazard_ent, kart_ent) = if kart_query.contains(*e2) { (*e1, *e2) }
+                                         else if kart_query.contains(*e1) { (*e2, *e1) }
+                                         else { continue; };
+
+            // Shells don't hit whoever threw them
+            let is_hazard = banana_query.contains(hazard_ent)
+                || shell_query.get(hazard_ent).is_ok_and(|shell| shell.owner != kart_ent);
+
+            if is_hazard {
+                hit_events.send(KartHit { kart: kart_ent });
+                commands.entity(hazard_ent).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn apply_kart_hits(
+    mut hit_events: EventReader<KartHit>,
+    mut kart_query: Query<(&mut Kart, &mut Velocity)>,
+) {
+    for hit in hit_events.read() {
+        if let Ok((mut kart, mut velocity)) = kart_query.get_mut(hit.kart) {
+            // Stars make the kart immune
+            if kart.star_timer > 0.0 {
+                continue;
+            }
+            kart.spinout_timer = 1.2;
+            kart.is_boosting = false;
+            kart.boost_timer = 0.0;
+            velocity.linvel *= 0.3;
+            info!("Kart hit!");
+        }
+    }
+}
+
 fn animate_objects(mut query: Query<&mut Transform, With<Rotating>>, time: Res<Time>) {
     for mut transform in query.iter_mut() {
         transform.rotate_y(2.0 * time.delta_secs());
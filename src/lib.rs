@@ -0,0 +1,20 @@
+pub mod player;
+pub mod track;
+pub mod ui;
+pub mod sounds;
+pub mod logic;
+pub mod items;
+pub mod config;
+pub mod input;
+pub mod net;
+pub mod lobby;
+pub mod select;
+pub mod menu;
+pub mod controls;
+pub mod battle;
+pub mod grand_prix;
+pub mod save;
+pub mod time_trial;
+pub mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use serde::{Deserialize, Serialize};
+use crate::logic::{RaceConfig, RacePhase};
+use crate::player::{KartBuild, LocalPlayer};
+use crate::track::{race_tracks, LoadTrack, DEFAULT_TRACK};
+
+pub const DEFAULT_LOBBY_PORT: u16 = 7000;
+pub const CHARACTERS: &[&str] = &["Mario", "Luigi", "Peach", "Toad"];
+pub const MAX_ROOM_PLAYERS: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoomSettings {
+    pub track: String,
+    pub race: RaceConfig,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        Self { track: DEFAULT_TRACK.to_string(), race: RaceConfig::default() }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum LobbyRequest {
+    CreateRoom { name: String, settings: RoomSettings },
+    JoinRoom { code: String, name: String },
+    // Host only
+    UpdateSettings { settings: RoomSettings },
+    PickCharacter { character: String },
+    SetReady { ready: bool },
+    // Host only, once everybody else is ready
+    StartRace,
+    Leave,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LobbyPlayer {
+    pub id: u32,
+    pub name: String,
+    pub character: String,
+    pub ready: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoomInfo {
+    pub code: String,
+    pub host: u32,
+    pub settings: RoomSettings,
+    pub players: Vec<LobbyPlayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum LobbyEvent {
+    Joined { player_id: u32, room: RoomInfo },
+    RoomUpdated { room: RoomInfo },
+    // Race server is up: native clients use the UDP address, the web build the WebSocket one
+    RaceStarting { udp_addr: String, ws_addr: String, settings: RoomSettings },
+    Error { message: String },
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct LobbyConnection {
+    socket: tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LobbyConnection {
+    pub fn connect(url: &str) -> Result<Self, String> {
+        let (socket, _) = tungstenite::connect(url).map_err(|err| err.to_string())?;
+        if let tungstenite::stream::MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream.set_nonblocking(true).map_err(|err| err.to_string())?;
+        }
+        Ok(Self { socket })
+    }
+
+    fn send(&mut self, request: &LobbyRequest) {
+        if let Ok(json) = serde_json::to_string(request) {
+            let _ = self.socket.send(tungstenite::Message::text(json));
+        }
+    }
+
+    fn receive(&mut self) -> Vec<LobbyEvent> {
+        crate::net::read_websocket(&mut self.socket).unwrap_or_default()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct LobbyConnection {
+    channel: crate::net::WebSocketChannel,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl LobbyConnection {
+    pub fn connect(url: &str) -> Result<Self, String> {
+        let channel = crate::net::WebSocketChannel::open(url).map_err(|err| format!("{:?}", err))?;
+        Ok(Self { channel })
+    }
+
+    fn send(&mut self, request: &LobbyRequest) {
+        if let Ok(json) = serde_json::to_string(request) {
+            self.channel.send_text(&json);
+        }
+    }
+
+    fn receive(&mut self) -> Vec<LobbyEvent> {
+        self.channel.drain().into_iter()
+            .filter_map(|text| serde_json::from_str(&text).ok())
+            .collect()
+    }
+}
+
+#[derive(PartialEq)]
+enum LobbyScreen {
+    Menu,
+    EnteringCode(String),
+    InRoom,
+}
+
+#[derive(Resource)]
+struct LobbyClient {
+    name: String,
+    screen: LobbyScreen,
+    player_id: Option<u32>,
+    room: Option<RoomInfo>,
+    character: usize,
+    status: String,
+}
+
+// Character picked in the lobby, for the race that follows
+#[derive(Resource)]
+pub struct SelectedCharacter(pub String);
+
+#[derive(Component)]
+struct LobbyUi;
+
+#[derive(Component)]
+struct LobbyText;
+
+// Hosted races: create or join a room, pick a character, ready up, and the host starts everyone into the countdown
+pub struct LobbyClientPlugin {
+    pub url: String,
+}
+
+impl Plugin for LobbyClientPlugin {
+    fn build(&self, app: &mut App) {
+        let status = match LobbyConnection::connect(&self.url) {
+            Ok(connection) => {
+                app.insert_non_send_resource(connection);
+                format!("Connected to {}", self.url)
+            }
+            Err(err) => format!("Can't reach lobby {}: {}", self.url, err),
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let name = std::env::var("USER").unwrap_or_else(|_| "Player".to_string());
+        #[cfg(target_arch = "wasm32")]
+        let name = "Player".to_string();
+
+        app.insert_resource(LobbyClient {
+               name,
+               screen: LobbyScreen::Menu,
+               player_id: None,
+               room: None,
+               character: 0,
+               status,
+           })
+           .insert_resource(SelectedCharacter(CHARACTERS[0].to_string()))
+           .insert_state(RacePhase::Lobby)
+           .add_systems(Startup, setup_lobby_ui)
+           .add_systems(Update, (
+               (
+                   receive_lobby_events,
+                   lobby_input,
+               ).chain().run_if(|connection: Option<NonSend<LobbyConnection>>| connection.is_some()),
+               quit_without_lobby.run_if(|connection: Option<NonSend<LobbyConnection>>| connection.is_none()),
+               update_lobby_text,
+           ).chain());
+    }
+}
+
+fn setup_lobby_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
+            // Above the race HUD
+            GlobalZIndex(10),
+            LobbyUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font: asset_server.load("fonts/HK.ttf"),
+                    font_size: 36.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                LobbyText,
+            ));
+        });
+}
+
+fn receive_lobby_events(
+    mut commands: Commands,
+    mut connection: NonSendMut<LobbyConnection>,
+    mut lobby: ResMut<LobbyClient>,
+    mut race_config: ResMut<RaceConfig>,
+    ui_query: Query<Entity, With<LobbyUi>>,
+    local_query: Query<Entity, With<LocalPlayer>>,
+    mut load_events: EventWriter<LoadTrack>,
+) {
+    for event in connection.receive() {
+        match event {
+            LobbyEvent::Joined { player_id, room } => {
+                lobby.status = format!("Joined room {}", room.code);
+                lobby.player_id = Some(player_id);
+                lobby.room = Some(room);
+                lobby.screen = LobbyScreen::InRoom;
+            }
+            LobbyEvent::RoomUpdated { room } => {
+                lobby.room = Some(room);
+            }
+            LobbyEvent::Error { message } => {
+                lobby.status = message;
+            }
+            LobbyEvent::RaceStarting { udp_addr, ws_addr, settings } => {
+                info!("Race starting on {} / {}", udp_addr, ws_addr);
+                *race_config = settings.race;
+                // Same track as the race server
+                load_events.send(LoadTrack(settings.track));
+                commands.insert_resource(SelectedCharacter(CHARACTERS[lobby.character].to_string()));
+                for kart in local_query.iter() {
+                    commands.entity(kart).insert(KartBuild { character: CHARACTERS[lobby.character].to_string(), ..default() });
+                }
+
+                let addr = if cfg!(target_arch = "wasm32") { ws_addr } else { udp_addr };
+                crate::net::connect_to_server(&mut commands, addr);
+
+                // The race server takes it from here (countdown starts once everyone joined)
+                for entity in ui_query.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                lobby.room = None;
+            }
+        }
+    }
+}
+
+fn lobby_input(
+    mut connection: NonSendMut<LobbyConnection>,
+    mut lobby: ResMut<LobbyClient>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut key_events: EventReader<KeyboardInput>,
+) {
+    let lobby = &mut *lobby;
+
+    match &mut lobby.screen {
+        LobbyScreen::Menu => {
+            if keyboard.just_pressed(KeyCode::KeyC) {
+                connection.send(&LobbyRequest::CreateRoom { name: lobby.name.clone(), settings: RoomSettings::default() });
+            } else if keyboard.just_pressed(KeyCode::KeyJ) {
+                lobby.screen = LobbyScreen::EnteringCode(String::new());
+                // Don't type the J that opened the prompt
+                key_events.clear();
+            }
+        }
+        LobbyScreen::EnteringCode(code) => {
+            for event in key_events.read() {
+                if event.state != ButtonState::Pressed {
+                    continue;
+                }
+                match &event.logical_key {
+                    Key::Character(c) if code.len() < 4 => code.push_str(&c.to_uppercase()),
+                    Key::Backspace => { code.pop(); }
+                    Key::Enter => {
+                        connection.send(&LobbyRequest::JoinRoom { code: code.clone(), name: lobby.name.clone() });
+                    }
+                    Key::Escape => {
+                        lobby.screen = LobbyScreen::Menu;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        LobbyScreen::InRoom => {
+            let Some(room) = &lobby.room else { return; };
+            let is_host = lobby.player_id == Some(room.host);
+
+            if keyboard.just_pressed(KeyCode::ArrowLeft) || keyboard.just_pressed(KeyCode::ArrowRight) {
+                let step = if keyboard.just_pressed(KeyCode::ArrowLeft) { CHARACTERS.len() - 1 } else { 1 };
+                lobby.character = (lobby.character + step) % CHARACTERS.len();
+                connection.send(&LobbyRequest::PickCharacter { character: CHARACTERS[lobby.character].to_string() });
+            }
+
+            if keyboard.just_pressed(KeyCode::Enter) {
+                let ready = room.players.iter().find(|p| Some(p.id) == lobby.player_id).is_some_and(|p| p.ready);
+                connection.send(&LobbyRequest::SetReady { ready: !ready });
+            }
+
+            if is_host {
+                let mut settings = room.settings.clone();
+                let mut changed = false;
+                if keyboard.just_pressed(KeyCode::KeyL) {
+                    settings.race.laps = settings.race.laps % 9 + 1;
+                    changed = true;
+                }
+                if keyboard.just_pressed(KeyCode::KeyK) {
+                    settings.race.cc = settings.race.cc.next();
+                    changed = true;
+                }
+                if keyboard.just_pressed(KeyCode::KeyT) {
+                    let tracks = race_tracks();
+                    let current = tracks.iter().position(|t| *t == settings.track).unwrap_or(0);
+                    settings.track = tracks[(current + 1) % tracks.len()].to_string();
+                    changed = true;
+                }
+                if changed {
+                    connection.send(&LobbyRequest::UpdateSettings { settings });
+                }
+                if keyboard.just_pressed(KeyCode::KeyS) {
+                    connection.send(&LobbyRequest::StartRace);
+                }
+            }
+
+            if keyboard.just_pressed(KeyCode::Escape) {
+                connection.send(&LobbyRequest::Leave);
+                lobby.room = None;
+                lobby.player_id = None;
+                lobby.screen = LobbyScreen::Menu;
+            }
+        }
+    }
+}
+
+// Nothing to do online without the lobby, the status says why
+fn quit_without_lobby(keyboard: Res<ButtonInput<KeyCode>>, mut exit: EventWriter<AppExit>) {
+    if keyboard.just_pressed(KeyCode::Escape) {
+        exit.send(AppExit::Success);
+    }
+}
+
+fn update_lobby_text(
+    lobby: Res<LobbyClient>,
+    connection: Option<NonSend<LobbyConnection>>,
+    mut text_query: Query<&mut Text, With<LobbyText>>,
+) {
+    let Ok(mut text) = text_query.get_single_mut() else { return; };
+
+    let body = match (&lobby.screen, &lobby.room) {
+        _ if connection.is_none() => "ONLINE LOBBY\n\n[Esc] Quit".to_string(),
+        (LobbyScreen::Menu, _) => "ONLINE LOBBY\n\n[C] Create a room\n[J] Join with a code".to_string(),
+        (LobbyScreen::EnteringCode(code), _) => format!("ROOM CODE: {}_\n\n[Enter] Join   [Esc] Back", code),
+        (LobbyScreen::InRoom, Some(room)) => {
+            let is_host = lobby.player_id == Some(room.host);
+            let mut lines = vec![
+                format!("ROOM {}", room.code),
+                format!("{} - {} laps - {}", room.settings.track, room.settings.race.laps, room.settings.race.cc.label()),
+                String::new(),
+            ];
+            for player in &room.players {
+                let host = if player.id == room.host { " (host)" } else { "" };
+                let ready = if player.ready || player.id == room.host { "READY" } else { "..." };
+                lines.push(format!("{}{} - {} - {}", player.name, host, player.character, ready));
+            }
+            lines.push(String::new());
+            lines.push("[<-/->] Character   [Enter] Ready".to_string());
+            if is_host {
+                lines.push("[L] Laps   [K] CC   [T] Track   [S] Start".to_string());
+            }
+            lines.push("[Esc] Leave".to_string());
+            lines.join("\n")
+        }
+        (LobbyScreen::InRoom, None) => "Waiting for the room...".to_string(),
+    };
+
+    let content = format!("{}\n\n{}", body, lobby.status);
+    if text.0 != content {
+        text.0 = content;
+    }
+}
@@ -1,15 +1,126 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::track::{TrackDefinition, TrackEntity, TrackSpline};
 
 pub struct LogicPlugin;
 
 impl Plugin for LogicPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_checkpoints)
-           .add_systems(Update, handle_checkpoint_collision);
+        app.init_resource::<RaceConfig>()
+           .init_resource::<GameMode>()
+           .init_resource::<FinishOrder>()
+           .init_resource::<RaceClock>()
+           .insert_resource(Countdown { remaining: COUNTDOWN_SECONDS })
+           .init_state::<RacePhase>()
+           .add_event::<CheckpointPassed>()
+           .add_event::<LapCompleted>()
+           .add_event::<RaceFinished>()
+           .add_systems(OnEnter(RacePhase::Countdown), reset_countdown)
+           .add_systems(Update, (
+               spawn_checkpoints.run_if(resource_exists_and_changed::<TrackDefinition>),
+               (handle_checkpoint_collision, update_race_positions).chain(),
+               tick_countdown.run_if(in_state(RacePhase::Countdown)),
+               tick_race_clock.run_if(in_state(RacePhase::Racing)),
+           ));
     }
 }
 
+pub const COUNTDOWN_SECONDS: f32 = 3.0;
+
+// Lobby: waiting for players, karts frozen. Countdown: on the grid. Racing: go!
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum RacePhase {
+    Lobby,
+    #[default]
+    Countdown,
+    Racing,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum CcClass {
+    Cc50,
+    Cc100,
+    #[default]
+    Cc150,
+}
+
+impl CcClass {
+    pub fn speed_multiplier(self) -> f32 {
+        match self {
+            CcClass::Cc50 => 0.75,
+            CcClass::Cc100 => 0.88,
+            CcClass::Cc150 => 1.0,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CcClass::Cc50 => "50cc",
+            CcClass::Cc100 => "100cc",
+            CcClass::Cc150 => "150cc",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            CcClass::Cc50 => CcClass::Cc100,
+            CcClass::Cc100 => CcClass::Cc150,
+            CcClass::Cc150 => CcClass::Cc50,
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim_end_matches("cc") {
+            "50" => Some(CcClass::Cc50),
+            "100" => Some(CcClass::Cc100),
+            "150" => Some(CcClass::Cc150),
+            _ => None,
+        }
+    }
+}
+
+// Picked on the main menu
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GameMode {
+    GrandPrix,
+    TimeTrial,
+    #[default]
+    Versus,
+    Battle,
+    CoinRunners,
+}
+
+impl GameMode {
+    // Played in an arena: no laps, no checkpoints
+    pub fn is_arena(self) -> bool {
+        matches!(self, GameMode::Battle | GameMode::CoinRunners)
+    }
+}
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct RaceConfig {
+    pub laps: usize,
+    pub cc: CcClass,
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        Self { laps: 3, cc: CcClass::default() }
+    }
+}
+
+#[derive(Resource)]
+pub struct Countdown {
+    pub remaining: f32,
+}
+
+// Time since the green light
+#[derive(Resource, Default)]
+pub struct RaceClock {
+    pub elapsed: f32,
+}
+
 #[derive(Component)]
 pub struct Checkpoint {
     pub index: usize,
@@ -20,23 +131,83 @@ pub struct PlayerStats {
     pub current_lap: usize,
     pub last_checkpoint: i32,
     pub coin_count: usize,
+    pub finished: bool,
+    // Seconds added to the race time (respawns)
+    pub penalty: f32,
+    // Race time at the finish, penalty included
+    pub finish_time: Option<f32>,
 }
 
-fn spawn_checkpoints(mut commands: Commands) {
-    let positions = vec![
-        Vec3::new(10.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 10.0),
-        Vec3::new(-10.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, -10.0),
-    ];
+impl Default for PlayerStats {
+    fn default() -> Self {
+        Self {
+            current_lap: 1,
+            last_checkpoint: -1,
+            coin_count: 0,
+            finished: false,
+            penalty: 0.0,
+            finish_time: None,
+        }
+    }
+}
+
+// 1 = leader
+#[derive(Component)]
+pub struct RacePosition(pub usize);
+
+// Finished karts by race time, penalties included: a kart crossing the line later can still place ahead
+#[derive(Resource, Default)]
+pub struct FinishOrder(pub Vec<Entity>);
+
+// Every checkpoint counted, in sequence (the start line included)
+#[derive(Event)]
+pub struct CheckpointPassed {
+    pub kart: Entity,
+    pub index: usize,
+}
+
+#[derive(Event)]
+pub struct LapCompleted {
+    pub kart: Entity,
+    pub lap: usize,
+}
+
+#[derive(Event)]
+pub struct RaceFinished {
+    pub kart: Entity,
+    pub position: usize,
+}
+
+fn reset_countdown(mut countdown: ResMut<Countdown>, mut clock: ResMut<RaceClock>) {
+    countdown.remaining = COUNTDOWN_SECONDS;
+    clock.elapsed = 0.0;
+}
+
+fn tick_countdown(
+    mut countdown: ResMut<Countdown>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+    time: Res<Time>,
+) {
+    countdown.remaining -= time.delta_secs();
+    if countdown.remaining <= 0.0 {
+        next_phase.set(RacePhase::Racing);
+        info!("GO!");
+    }
+}
 
-    for (i, pos) in positions.into_iter().enumerate() {
+fn tick_race_clock(mut clock: ResMut<RaceClock>, time: Res<Time>) {
+    clock.elapsed += time.delta_secs();
+}
+
+fn spawn_checkpoints(mut commands: Commands, track: Res<TrackDefinition>) {
+    for (i, pos) in track.checkpoints.iter().enumerate() {
         commands.spawn((
-            Transform::from_translation(pos),
-            Collider::cuboid(10.0, 10.0, 1.0),
+            Transform::from_translation(Vec3::from(*pos)),
+            Collider::cuboid(track.checkpoint_size[0], track.checkpoint_size[1], track.checkpoint_size[2]),
             Sensor,
             Checkpoint { index: i },
             Name::new(format!("Checkpoint {}", i)),
+            TrackEntity,
         ));
     }
 }
@@ -45,22 +216,105 @@ fn handle_checkpoint_collision(
     mut collision_events: EventReader<CollisionEvent>,
     checkpoint_query: Query<&Checkpoint>,
     mut player_query: Query<&mut PlayerStats>,
+    config: Res<RaceConfig>,
+    clock: Res<RaceClock>,
+    mut finish_order: ResMut<FinishOrder>,
+    mut checkpoint_events: EventWriter<CheckpointPassed>,
+    mut lap_events: EventWriter<LapCompleted>,
+    mut finish_events: EventWriter<RaceFinished>,
 ) {
+    let checkpoint_count = checkpoint_query.iter().count() as i32;
+    if checkpoint_count == 0 {
+        return;
+    }
+
+    // Race times of everyone already home, in finishing order
+    let mut finish_times: Vec<f32> = finish_order.0.iter()
+        .map(|kart| player_query.get(*kart).ok().and_then(|stats| stats.finish_time).unwrap_or(0.0))
+        .collect();
+
     for event in collision_events.read() {
         if let CollisionEvent::Started(e1, e2, _) = event {
-            let (checkpoint_ent, player_ent) = if checkpoint_query.contains(*e1) { (*e1, *e2) } 
-                                              else if checkpoint_query.contains(*e2) { (*e2, *e1) } 
+            let (checkpoint_ent, player_ent) = if checkpoint_query.contains(*e1) { (*e1, *e2) }
+                                              else if checkpoint_query.contains(*e2) { (*e2, *e1) }
                                               else { continue; };
 
             if let Ok(checkpoint) = checkpoint_query.get(checkpoint_ent) {
                 if let Ok(mut stats) = player_query.get_mut(player_ent) {
-                    if checkpoint.index == 0 && stats.last_checkpoint == 3 {
-                        stats.current_lap += 1;
-                        info!("Lap {}!", stats.current_lap);
+                    // Checkpoints only count in sequence, so cutting the track doesn't skip ahead
+                    let index = checkpoint.index as i32;
+                    if stats.finished || index != (stats.last_checkpoint + 1) % checkpoint_count {
+                        continue;
+                    }
+
+                    if index == 0 && stats.last_checkpoint == checkpoint_count - 1 {
+                        lap_events.send(LapCompleted { kart: player_ent, lap: stats.current_lap });
+                        if stats.current_lap >= config.laps {
+                            let time = clock.elapsed + stats.penalty;
+                            let place = finish_times.iter().position(|other| *other > time).unwrap_or(finish_times.len());
+                            stats.finished = true;
+                            stats.finish_time = Some(time);
+                            finish_times.insert(place, time);
+                            finish_order.0.insert(place, player_ent);
+                            finish_events.send(RaceFinished { kart: player_ent, position: place + 1 });
+                            info!("Finished in position {} ({:.3}s, {:.0}s penalty)!", place + 1, time, stats.penalty);
+                        } else {
+                            stats.current_lap += 1;
+                            info!("Lap {}!", stats.current_lap);
+                        }
                     }
-                    stats.last_checkpoint = checkpoint.index as i32;
+                    stats.last_checkpoint = index;
+                    checkpoint_events.send(CheckpointPassed { kart: player_ent, index: checkpoint.index });
                 }
             }
         }
     }
 }
+
+fn update_race_positions(
+    mut kart_query: Query<(Entity, &Transform, &PlayerStats, &mut RacePosition)>,
+    checkpoint_query: Query<(&Checkpoint, &Transform)>,
+    spline: Res<TrackSpline>,
+    finish_order: Res<FinishOrder>,
+) {
+    let mut checkpoints: Vec<(usize, f32)> = checkpoint_query.iter()
+        .map(|(checkpoint, transform)| (checkpoint.index, spline.project(transform.translation)))
+        .collect();
+    if checkpoints.is_empty() {
+        return;
+    }
+    checkpoints.sort_by_key(|(index, _)| *index);
+    let n = checkpoints.len();
+
+    // Progress in "checkpoints passed", the fraction coming from the spline between the last and next checkpoint
+    let mut progress: Vec<(Entity, Option<usize>, f32)> = kart_query.iter()
+        .map(|(entity, transform, stats, _)| {
+            let place = finish_order.0.iter().position(|e| *e == entity);
+            let last = stats.last_checkpoint.rem_euclid(n as i32) as usize;
+            let next = (last + 1) % n;
+            let s_kart = spline.project(transform.translation);
+            let span = spline.delta(checkpoints[last].1, checkpoints[next].1);
+            let fraction = if span > 0.0 {
+                (spline.delta(checkpoints[last].1, s_kart) / span).min(1.0)
+            } else {
+                0.0
+            };
+            let passed = (stats.current_lap - 1) * n;
+            (entity, place, passed as f32 + stats.last_checkpoint as f32 + fraction)
+        })
+        .collect();
+
+    // Finished karts keep their finishing place, everyone else is ranked by progress
+    progress.sort_by(|a, b| match (a.1, b.1) {
+        (Some(pa), Some(pb)) => pa.cmp(&pb),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => b.2.total_cmp(&a.2),
+    });
+
+    for (i, (entity, _, _)) in progress.into_iter().enumerate() {
+        if let Ok((_, _, _, mut position)) = kart_query.get_mut(entity) {
+            position.0 = i + 1;
+        }
+    }
+}
@@ -1,22 +1,91 @@
-mod player;
-mod track;
-mod ui;
-mod sounds;
-mod logic;
-mod items;
-
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use bevy_panorbit_camera::PanOrbitCameraPlugin;
-use player::PlayerPlugin;
-use track::TrackPlugin;
-use ui::UiPlugin;
-use sounds::SoundsPlugin;
-use logic::LogicPlugin;
-use items::ItemsPlugin;
+use mariok_bevy::net;
+use mariok_bevy::headless;
+#[cfg(not(target_arch = "wasm32"))]
+use mariok_bevy::server;
+use mariok_bevy::player::PlayerPlugin;
+use mariok_bevy::track::{TrackPlugin, DEFAULT_TRACK};
+use mariok_bevy::ui::UiPlugin;
+use mariok_bevy::sounds::SoundEffectsPlugin;
+use mariok_bevy::logic::LogicPlugin;
+use mariok_bevy::items::ItemsPlugin;
+use mariok_bevy::input::InputPlugin;
+use mariok_bevy::player::LocalPlayers;
+use mariok_bevy::net::NetClientPlugin;
+use mariok_bevy::lobby::LobbyClientPlugin;
+use mariok_bevy::menu::MenuPlugin;
+use mariok_bevy::logic::{CcClass, RaceConfig};
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.as_str())
+}
+
+// Web build: `index.html?connect=ws://host:5001`
+#[cfg(target_arch = "wasm32")]
+fn web_query_param(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    web_sys::UrlSearchParams::new_with_str(&search).ok()?.get(name)
+}
 
 fn main() {
-    App::new()
+    let args: Vec<String> = std::env::args().collect();
+
+    // Race settings, also passed by the lobby to the servers it starts
+    let mut race = RaceConfig::default();
+    if let Some(laps) = arg_value(&args, "--laps").and_then(|n| n.parse().ok()) {
+        race.laps = laps;
+    }
+    if let Some(cc) = arg_value(&args, "--cc").and_then(CcClass::parse) {
+        race.cc = cc;
+    }
+
+    // `--server` runs the headless authoritative server instead of the game
+    #[cfg(not(target_arch = "wasm32"))]
+    if args.iter().any(|a| a == "--server") {
+        server::run(server::ServerConfig {
+            udp_addr: arg_value(&args, "--udp").map(String::from).unwrap_or(format!("0.0.0.0:{}", net::DEFAULT_UDP_PORT)),
+            ws_addr: arg_value(&args, "--ws").map(String::from).unwrap_or(format!("0.0.0.0:{}", net::DEFAULT_WS_PORT)),
+            expected_players: arg_value(&args, "--expect").and_then(|n| n.parse().ok()).unwrap_or(1),
+            track: arg_value(&args, "--track").unwrap_or(DEFAULT_TRACK).to_string(),
+            race,
+        });
+        return;
+    }
+
+    // `--headless` (or building with the `headless` feature) simulates a scripted race with no window or GPU
+    if cfg!(feature = "headless") || args.iter().any(|a| a == "--headless") {
+        let frames = arg_value(&args, "--frames").and_then(|n| n.parse().ok()).unwrap_or(3600);
+        headless::run(race, frames);
+        return;
+    }
+
+    // `--players N` starts an N player split-screen race
+    let players = arg_value(&args, "--players")
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(1)
+        .clamp(1, 4);
+
+    // `--connect 127.0.0.1:5000` joins an online race
+    #[cfg(not(target_arch = "wasm32"))]
+    let connect = arg_value(&args, "--connect").map(String::from);
+    #[cfg(target_arch = "wasm32")]
+    let connect = web_query_param("connect");
+
+    // `--lobby ws://127.0.0.1:7000` joins rooms on a lobby server (`?lobby=` on the web)
+    #[cfg(not(target_arch = "wasm32"))]
+    let lobby = arg_value(&args, "--lobby").map(String::from);
+    #[cfg(target_arch = "wasm32")]
+    let lobby = web_query_param("lobby");
+
+    // Local play starts on the main menu
+    let local = connect.is_none() && lobby.is_none();
+
+    let mut app = App::new();
+    app
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Mario Kart Bevy 0.15".into(),
@@ -33,14 +102,35 @@ fn main() {
         }))
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugins(PanOrbitCameraPlugin)
+        .insert_resource(LocalPlayers(players))
+        .insert_resource(race)
+        .add_plugins(InputPlugin)
         .add_plugins(TrackPlugin)
         .add_plugins(PlayerPlugin)
         .add_plugins(UiPlugin)
         // .add_plugins(SoundsPlugin)
+        .add_plugins(SoundEffectsPlugin)
         .add_plugins(LogicPlugin)
         .add_plugins(ItemsPlugin)
-        .add_systems(Startup, setup_scene)
-        .run();
+        .add_systems(Startup, setup_scene);
+
+    if local {
+        app.add_plugins(MenuPlugin);
+    }
+
+    // Online races are one player per machine
+    if let Some(addr) = connect {
+        app.insert_resource(LocalPlayers(1))
+           .add_plugins(NetClientPlugin { addr: Some(addr) });
+    }
+
+    if let Some(url) = lobby {
+        app.insert_resource(LocalPlayers(1))
+           .add_plugins(LobbyClientPlugin { url })
+           .add_plugins(NetClientPlugin { addr: None });
+    }
+
+    app.run();
 }
 
 fn setup_scene(mut commands: Commands) {
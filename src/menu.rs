@@ -0,0 +1,547 @@
+use bevy::prelude::*;
+use bevy::audio::{GlobalVolume, Volume};
+use bevy::window::{MonitorSelection, PresentMode, PrimaryWindow, WindowMode};
+use bevy_rapier3d::prelude::*;
+use crate::controls::{ControlsHelp, ControlsPlugin};
+use crate::grand_prix::GrandPrixPlugin;
+use crate::input::{key_name, InputBindings, KeyBindings, MenuInput};
+use crate::items::HeldItem;
+use crate::logic::{FinishOrder, GameMode, PlayerStats, RaceClock, RacePhase, RacePosition};
+use crate::player::{grid_transform, Kart, LocalPlayer, Respawn};
+use crate::battle::BattlePlugin;
+use crate::select::SelectPlugin;
+use crate::time_trial::TimeTrialPlugin;
+use crate::track::{LoadTrack, TrackDefinition, TrackVariant, DEFAULT_TRACK};
+
+// Main menu -> select screen -> race, with the pause and settings screens on top.
+// Local play only: online races have no pause and go straight to the lobby.
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<Screen>()
+           .init_state::<PauseState>()
+           .enable_state_scoped_entities::<Screen>()
+           .enable_state_scoped_entities::<PauseState>()
+           .init_resource::<MenuFocus>()
+           .init_resource::<Settings>()
+           .init_resource::<Rebinding>()
+           .add_event::<MenuAction>()
+           .add_plugins((SelectPlugin, ControlsPlugin, GrandPrixPlugin, TimeTrialPlugin, BattlePlugin))
+           .add_systems(OnEnter(Screen::MainMenu), spawn_main_menu)
+           .add_systems(OnEnter(Screen::Settings), spawn_settings_menu)
+           .add_systems(OnEnter(Screen::Race), load_default_track.run_if(resource_equals(GameMode::Versus).or(resource_equals(GameMode::TimeTrial))))
+           .add_systems(OnExit(Screen::Race), reset_race)
+           .add_systems(OnEnter(PauseState::Paused), (spawn_pause_menu, freeze_race))
+           .add_systems(OnEnter(PauseState::Settings), spawn_settings_menu)
+           .add_systems(OnEnter(PauseState::Running), unfreeze_race)
+           .add_systems(Update, (
+               capture_rebind,
+               navigate_menu,
+               (
+                   main_menu_actions.run_if(in_state(Screen::MainMenu)),
+                   pause_menu_actions.run_if(in_state(PauseState::Paused)),
+                   settings_actions.run_if(in_state(Screen::Settings).or(in_state(PauseState::Settings))),
+               ),
+               (update_settings_labels, highlight_menu),
+           ).chain().run_if(in_state(ControlsHelp::Hidden)))
+           .add_systems(Update, (
+               toggle_pause.run_if(in_state(Screen::Race)),
+               apply_settings,
+               line_up_on_grid.run_if(resource_changed::<TrackDefinition>),
+           ));
+
+        // Every screen starts with no actions left over from the one that opened it
+        for screen in [Screen::MainMenu, Screen::Settings, Screen::Select, Screen::Race, Screen::Standings, Screen::Podium, Screen::Results] {
+            app.add_systems(OnEnter(screen), clear_menu_actions);
+        }
+        for pause in [PauseState::Running, PauseState::Paused, PauseState::Settings] {
+            app.add_systems(OnEnter(pause), clear_menu_actions);
+        }
+    }
+}
+
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Screen {
+    #[default]
+    MainMenu,
+    Settings,
+    Select,
+    Race,
+    // Between Grand Prix races, and after the last one
+    Standings,
+    Podium,
+    // Time trial times and splits
+    Results,
+}
+
+// Settings opened from the pause menu keep the race frozen
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+    Settings,
+}
+
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct Settings {
+    // 0..=10
+    pub volume: u32,
+    pub shadows: bool,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    // Track variant for the next races (see TrackVariant)
+    pub mirror: bool,
+    pub reverse: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { volume: 8, shadows: true, fullscreen: false, vsync: true, mirror: false, reverse: false }
+    }
+}
+
+// Only one menu is on screen at a time, its buttons are numbered from the top
+#[derive(Component)]
+struct MenuButton(usize);
+
+#[derive(Component)]
+struct MenuLabel(usize);
+
+#[derive(Resource, Default)]
+struct MenuFocus(usize);
+
+#[derive(Event, Clone, Copy)]
+enum MenuAction {
+    Activate(usize),
+    // Left/right on a button
+    Adjust(usize, i32),
+    Back,
+}
+
+// Settings row waiting for a key press
+#[derive(Resource, Default)]
+struct Rebinding(Option<usize>);
+
+const MAIN_MENU: [&str; 7] = ["Grand Prix", "Time Trial", "Versus", "Battle", "Coin Runners", "Settings", "Quit"];
+const MODES: [GameMode; 5] = [GameMode::GrandPrix, GameMode::TimeTrial, GameMode::Versus, GameMode::Battle, GameMode::CoinRunners];
+const PAUSE_MENU: [&str; 4] = ["Resume", "Controls", "Settings", "Quit to main menu"];
+
+const BINDING_LABELS: [&str; 7] = ["Accelerate", "Brake", "Steer left", "Steer right", "Jump / drift", "Use item", "Reset"];
+// Volume, shadows, fullscreen, vsync, mirror, reverse, then the bindings and Back
+const FIRST_BINDING_ROW: usize = 6;
+const SETTINGS_ROWS: usize = FIRST_BINDING_ROW + BINDING_LABELS.len() + 1;
+
+const BUTTON_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.8);
+const FOCUS_COLOR: Color = Color::srgb(0.85, 0.15, 0.1);
+
+// A title and a column of buttons over everything, with its own UI camera so split-screen viewports don't clip it
+fn spawn_menu(commands: &mut Commands, asset_server: &AssetServer, title: &str, items: Vec<String>, scope: impl Bundle + Clone) {
+    commands.insert_resource(MenuFocus(0));
+    let font = asset_server.load("fonts/HK.ttf");
+
+    let camera = commands.spawn((
+        Camera2d,
+        Camera { order: 50, clear_color: ClearColorConfig::None, ..default() },
+        scope.clone(),
+    )).id();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+            TargetCamera(camera),
+            scope,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(title),
+                TextFont { font: font.clone(), font_size: 72.0, ..default() },
+                TextColor(Color::srgb(1.0, 0.85, 0.0)),
+                Node { margin: UiRect::bottom(Val::Px(30.0)), ..default() },
+            ));
+
+            for (i, label) in items.into_iter().enumerate() {
+                parent.spawn((
+                    Button,
+                    Node {
+                        width: Val::Px(560.0),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON_COLOR),
+                    MenuButton(i),
+                )).with_children(|button| {
+                    button.spawn((
+                        Text::new(label),
+                        TextFont { font: font.clone(), font_size: 32.0, ..default() },
+                        TextColor(Color::WHITE),
+                        MenuLabel(i),
+                    ));
+                });
+            }
+        });
+}
+
+fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let items = MAIN_MENU.iter().map(|item| item.to_string()).collect();
+    spawn_menu(&mut commands, &asset_server, "Mario Kart Bevy", items, StateScoped(Screen::MainMenu));
+}
+
+fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let items = PAUSE_MENU.iter().map(|item| item.to_string()).collect();
+    spawn_menu(&mut commands, &asset_server, "Paused", items, StateScoped(PauseState::Paused));
+}
+
+// Opened from the main menu or the pause menu, labels are filled in by update_settings_labels
+fn spawn_settings_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    screen: Res<State<Screen>>,
+) {
+    let items = vec![String::new(); SETTINGS_ROWS];
+    if *screen.get() == Screen::Settings {
+        spawn_menu(&mut commands, &asset_server, "Settings", items, StateScoped(Screen::Settings));
+    } else {
+        spawn_menu(&mut commands, &asset_server, "Settings", items, StateScoped(PauseState::Settings));
+    }
+}
+
+// Keyboard, gamepad and mouse all move the same focus
+fn navigate_menu(
+    menu: Res<MenuInput>,
+    mut focus: ResMut<MenuFocus>,
+    interaction_query: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    button_query: Query<(), With<MenuButton>>,
+    mut actions: EventWriter<MenuAction>,
+) {
+    let len = button_query.iter().count();
+    if len == 0 {
+        return;
+    }
+    // A menu with fewer buttons just replaced a longer one
+    if focus.0 >= len {
+        focus.0 = 0;
+    }
+
+    if menu.up {
+        focus.0 = (focus.0 + len - 1) % len;
+    }
+    if menu.down {
+        focus.0 = (focus.0 + 1) % len;
+    }
+
+    for (interaction, button) in interaction_query.iter() {
+        match interaction {
+            Interaction::Hovered => focus.0 = button.0,
+            Interaction::Pressed => {
+                focus.0 = button.0;
+                actions.send(MenuAction::Activate(button.0));
+            }
+            Interaction::None => {}
+        }
+    }
+
+    if menu.confirm {
+        actions.send(MenuAction::Activate(focus.0));
+    }
+    if menu.left {
+        actions.send(MenuAction::Adjust(focus.0, -1));
+    }
+    if menu.right {
+        actions.send(MenuAction::Adjust(focus.0, 1));
+    }
+    if menu.back {
+        actions.send(MenuAction::Back);
+    }
+}
+
+// The action that switched screens is still buffered for a frame, the next screen's handler would read it too
+fn clear_menu_actions(mut actions: ResMut<Events<MenuAction>>) {
+    actions.clear();
+}
+
+fn highlight_menu(focus: Res<MenuFocus>, mut query: Query<(&MenuButton, &mut BackgroundColor)>) {
+    for (button, mut color) in query.iter_mut() {
+        color.0 = if button.0 == focus.0 { FOCUS_COLOR } else { BUTTON_COLOR };
+    }
+}
+
+fn main_menu_actions(
+    mut actions: EventReader<MenuAction>,
+    mut mode: ResMut<GameMode>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for action in actions.read() {
+        let MenuAction::Activate(index) = *action else { continue; };
+        // The modes, then Settings and Quit
+        if let Some(picked) = MODES.get(index) {
+            *mode = *picked;
+            info!("{:?} selected", *mode);
+            next_screen.set(Screen::Select);
+        } else if index == MODES.len() {
+            next_screen.set(Screen::Settings);
+        } else {
+            exit.send(AppExit::Success);
+        }
+    }
+}
+
+fn toggle_pause(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    pause: Res<State<PauseState>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+) {
+    let pressed = keyboard.just_pressed(KeyCode::Escape)
+        || gamepads.iter().any(|gamepad| gamepad.just_pressed(GamepadButton::Start));
+    // Closing is handled by the pause menu itself (Back or Resume)
+    if pressed && *pause.get() == PauseState::Running {
+        next_pause.set(PauseState::Paused);
+    }
+}
+
+fn pause_menu_actions(
+    mut actions: EventReader<MenuAction>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut next_help: ResMut<NextState<ControlsHelp>>,
+) {
+    for action in actions.read() {
+        match *action {
+            MenuAction::Activate(0) | MenuAction::Back => next_pause.set(PauseState::Running),
+            MenuAction::Activate(1) => next_help.set(ControlsHelp::Shown),
+            MenuAction::Activate(2) => next_pause.set(PauseState::Settings),
+            MenuAction::Activate(3) => {
+                next_pause.set(PauseState::Running);
+                next_screen.set(Screen::MainMenu);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Race clock, countdown, timers and physics all run on virtual time and the Rapier pipeline
+fn freeze_race(mut time: ResMut<Time<Virtual>>, mut rapier_query: Query<&mut RapierConfiguration>) {
+    time.pause();
+    for mut config in rapier_query.iter_mut() {
+        config.physics_pipeline_active = false;
+    }
+}
+
+fn unfreeze_race(mut time: ResMut<Time<Virtual>>, mut rapier_query: Query<&mut RapierConfiguration>) {
+    time.unpause();
+    for mut config in rapier_query.iter_mut() {
+        config.physics_pipeline_active = true;
+    }
+}
+
+// Leaving a race puts everyone back on the grid for the next one
+// A finished or abandoned cup may have left another track loaded, or mirror/reverse changed since
+fn load_default_track(track: Res<TrackDefinition>, variant: Res<TrackVariant>, mut load_events: EventWriter<LoadTrack>) {
+    if track.name != DEFAULT_TRACK || track.variant != *variant {
+        load_events.send(LoadTrack(DEFAULT_TRACK.to_string()));
+    }
+}
+
+// A track loaded before the start (possibly mirrored or reversed) gets everyone onto its grid
+fn line_up_on_grid(
+    mut kart_query: Query<(&LocalPlayer, &mut Transform, &mut Velocity, &mut Kart)>,
+    track: Res<TrackDefinition>,
+    phase: Res<State<RacePhase>>,
+) {
+    if *phase.get() == RacePhase::Racing {
+        return;
+    }
+    for (local, mut transform, mut velocity, mut kart) in kart_query.iter_mut() {
+        let (start_pos, start_rot) = grid_transform(local.index, &track);
+        *transform = Transform::from_translation(start_pos).with_rotation(start_rot);
+        *velocity = Velocity::zero();
+        *kart = Kart::new(start_pos, start_rot);
+    }
+}
+
+fn reset_race(
+    mut kart_query: Query<(Entity, &LocalPlayer, &mut Transform, &mut Velocity, &mut Kart, &mut PlayerStats, &mut HeldItem, &mut RacePosition)>,
+    mut commands: Commands,
+    mut finish_order: ResMut<FinishOrder>,
+    mut clock: ResMut<RaceClock>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+    track: Res<TrackDefinition>,
+) {
+    for (entity, local, mut transform, mut velocity, mut kart, mut stats, mut held, mut position) in kart_query.iter_mut() {
+        let (start_pos, start_rot) = grid_transform(local.index, &track);
+        *transform = Transform::from_translation(start_pos).with_rotation(start_rot);
+        *velocity = Velocity::zero();
+        *kart = Kart::new(start_pos, start_rot);
+        *stats = PlayerStats::default();
+        *held = HeldItem::default();
+        position.0 = 1;
+        commands.entity(entity).remove::<Respawn>();
+    }
+    finish_order.0.clear();
+    clock.elapsed = 0.0;
+    next_phase.set(RacePhase::Lobby);
+}
+
+fn binding(keys: &KeyBindings, binding: usize) -> &Vec<KeyCode> {
+    match binding {
+        0 => &keys.up,
+        1 => &keys.down,
+        2 => &keys.left,
+        3 => &keys.right,
+        4 => &keys.jump,
+        5 => &keys.use_item,
+        _ => &keys.reset,
+    }
+}
+
+fn binding_mut(keys: &mut KeyBindings, binding: usize) -> &mut Vec<KeyCode> {
+    match binding {
+        0 => &mut keys.up,
+        1 => &mut keys.down,
+        2 => &mut keys.left,
+        3 => &mut keys.right,
+        4 => &mut keys.jump,
+        5 => &mut keys.use_item,
+        _ => &mut keys.reset,
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value { "On" } else { "Off" }
+}
+
+fn settings_label(row: usize, settings: &Settings, bindings: &InputBindings, rebinding: &Rebinding) -> String {
+    match row {
+        0 => format!("Volume:  < {}% >", settings.volume * 10),
+        1 => format!("Shadows:  < {} >", on_off(settings.shadows)),
+        2 => format!("Fullscreen:  < {} >", on_off(settings.fullscreen)),
+        3 => format!("VSync:  < {} >", on_off(settings.vsync)),
+        4 => format!("Mirror mode:  < {} >", on_off(settings.mirror)),
+        5 => format!("Reverse:  < {} >", on_off(settings.reverse)),
+        row if row < SETTINGS_ROWS - 1 => {
+            let index = row - FIRST_BINDING_ROW;
+            if rebinding.0 == Some(row) {
+                format!("{}:  press a key...", BINDING_LABELS[index])
+            } else {
+                let names: Vec<String> = binding(&bindings.keyboard_full, index).iter().map(|key| key_name(*key)).collect();
+                format!("{}:  {}", BINDING_LABELS[index], names.join(" / "))
+            }
+        }
+        _ => "Back".to_string(),
+    }
+}
+
+fn settings_actions(
+    mut actions: EventReader<MenuAction>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    screen: Res<State<Screen>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+) {
+    for action in actions.read() {
+        let (row, step) = match *action {
+            MenuAction::Activate(row) => (row, 1),
+            MenuAction::Adjust(row, step) => (row, step),
+            MenuAction::Back => (SETTINGS_ROWS - 1, 0),
+        };
+        match row {
+            0 => settings.volume = (settings.volume as i32 + step).clamp(0, 10) as u32,
+            1 => settings.shadows = !settings.shadows,
+            2 => settings.fullscreen = !settings.fullscreen,
+            3 => settings.vsync = !settings.vsync,
+            4 => settings.mirror = !settings.mirror,
+            5 => settings.reverse = !settings.reverse,
+            row if row < SETTINGS_ROWS - 1 => {
+                if matches!(action, MenuAction::Activate(_)) {
+                    rebinding.0 = Some(row);
+                }
+            }
+            _ if matches!(action, MenuAction::Adjust(..)) => {}
+            _ => {
+                if *screen.get() == Screen::Settings {
+                    next_screen.set(Screen::MainMenu);
+                } else {
+                    next_pause.set(PauseState::Paused);
+                }
+            }
+        }
+    }
+}
+
+// The next key pressed becomes the main binding for the solo keyboard, Escape cancels
+fn capture_rebind(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<InputBindings>,
+    mut menu: ResMut<MenuInput>,
+) {
+    let Some(row) = rebinding.0 else { return; };
+    let Some(key) = keyboard.get_just_pressed().next().copied() else { return; };
+
+    if key != KeyCode::Escape {
+        let keys = binding_mut(&mut bindings.keyboard_full, row - FIRST_BINDING_ROW);
+        keys.retain(|bound| *bound != key);
+        keys.insert(0, key);
+        info!("{} bound to {:?}", BINDING_LABELS[row - FIRST_BINDING_ROW], key);
+    }
+    rebinding.0 = None;
+    // That press was for the binding, not the menu
+    *menu = MenuInput::default();
+}
+
+fn update_settings_labels(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    bindings: Res<InputBindings>,
+    screen: Res<State<Screen>>,
+    pause: Res<State<PauseState>>,
+    mut label_query: Query<(&mut Text, &MenuLabel)>,
+) {
+    if *screen.get() != Screen::Settings && *pause.get() != PauseState::Settings {
+        return;
+    }
+    for (mut text, label) in label_query.iter_mut() {
+        let value = settings_label(label.0, &settings, &bindings, &rebinding);
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut volume: Option<ResMut<GlobalVolume>>,
+    mut light_query: Query<&mut DirectionalLight>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut variant: ResMut<TrackVariant>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    // Picked up by the next track load, the race in progress keeps its layout
+    variant.set_if_neq(TrackVariant { mirror: settings.mirror, reverse: settings.reverse });
+    if let Some(volume) = volume.as_mut() {
+        volume.volume = Volume::new(settings.volume as f32 / 10.0);
+    }
+    for mut light in light_query.iter_mut() {
+        light.shadows_enabled = settings.shadows;
+    }
+    for mut window in window_query.iter_mut() {
+        window.mode = if settings.fullscreen { WindowMode::BorderlessFullscreen(MonitorSelection::Current) } else { WindowMode::Windowed };
+        window.present_mode = if settings.vsync { PresentMode::AutoVsync } else { PresentMode::AutoNoVsync };
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use bevy::gltf::GltfAssetLabel;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::input::KartInput;
+use crate::items::{Coin, HeldItem, Item, ItemBox, ItemRules, LayoutIndex};
+use crate::logic::{Countdown, PlayerStats, RacePhase};
+use crate::player::{spawn_kart, Kart, LocalPlayer};
+use crate::track::TrackEntity;
+
+pub const DEFAULT_UDP_PORT: u16 = 5000;
+pub const DEFAULT_WS_PORT: u16 = 5001;
+
+// Remote karts are drawn this far in the past so there are (almost) always two snapshots to blend
+const INTERPOLATION_DELAY: f32 = 0.1;
+// Predicted steps kept if the server stops answering
+const MAX_HISTORY: usize = 240;
+
+#[derive(Serialize, Deserialize)]
+pub enum ClientMessage {
+    Join,
+    Input { seq: u32, input: KartInput },
+    Leave,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome { player_id: u32 },
+    // Race full or already started
+    Rejected { reason: String },
+    Snapshot {
+        tick: u32,
+        phase: RacePhase,
+        countdown: f32,
+        karts: Vec<KartState>,
+        // Bananas and shells out on the track
+        items: Vec<ItemState>,
+        // Which boxes and coins of the track layout are still there, by LayoutIndex
+        boxes: Vec<bool>,
+        coins: Vec<bool>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ItemState {
+    // Server entity, stable for the life of the item
+    pub id: u64,
+    pub item: Item,
+    pub position: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KartState {
+    pub player_id: u32,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    pub linvel: [f32; 3],
+    // Last input the server applied before taking this snapshot
+    pub last_input_seq: u32,
+    pub lap: usize,
+    pub last_checkpoint: i32,
+    pub coins: usize,
+    pub item: Option<Item>,
+    pub boosting: bool,
+}
+
+impl KartState {
+    pub fn position(&self) -> Vec3 {
+        Vec3::from_array(self.position)
+    }
+
+    pub fn rotation(&self) -> Quat {
+        Quat::from_array(self.rotation)
+    }
+}
+
+// Server side WebSocket handshake, then non-blocking so reads never stall a tick
+#[cfg(not(target_arch = "wasm32"))]
+pub fn accept_websocket(stream: std::net::TcpStream) -> Option<tungstenite::WebSocket<std::net::TcpStream>> {
+    stream.set_nonblocking(false).ok()?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(2))).ok()?;
+    let socket = tungstenite::accept(stream).ok()?;
+    socket.get_ref().set_nonblocking(true).ok()?;
+    Some(socket)
+}
+
+// Drains every readable JSON message; `None` once the peer is gone
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_websocket<T: serde::de::DeserializeOwned, S: std::io::Read + std::io::Write>(socket: &mut tungstenite::WebSocket<S>) -> Option<Vec<T>> {
+    use tungstenite::{Error, Message};
+
+    let mut messages = Vec::new();
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if let Ok(message) = serde_json::from_str(&text) {
+                    messages.push(message);
+                }
+            }
+            Ok(Message::Close(_)) => return None,
+            Ok(_) => {}
+            Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => return Some(messages),
+            Err(_) => return None,
+        }
+    }
+}
+
+// Native clients talk UDP to the server
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ClientTransport {
+    socket: std::net::UdpSocket,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ClientTransport {
+    pub fn connect(addr: &str) -> std::io::Result<Self> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    fn send(&self, message: &ClientMessage) {
+        if let Ok(bytes) = serde_json::to_vec(message) {
+            let _ = self.socket.send(&bytes);
+        }
+    }
+
+    fn receive(&mut self) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+        let mut buf = [0u8; 65536];
+        while let Ok(len) = self.socket.recv(&mut buf) {
+            if let Ok(message) = serde_json::from_slice(&buf[..len]) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+}
+
+// Browser WebSocket with incoming text frames queued until the next frame polls them
+#[cfg(target_arch = "wasm32")]
+pub struct WebSocketChannel {
+    socket: web_sys::WebSocket,
+    inbox: std::rc::Rc<std::cell::RefCell<VecDeque<String>>>,
+    _on_message: wasm_bindgen::closure::Closure<dyn FnMut(web_sys::MessageEvent)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebSocketChannel {
+    pub fn open(url: &str) -> Result<Self, wasm_bindgen::JsValue> {
+        use wasm_bindgen::JsCast;
+
+        let socket = web_sys::WebSocket::new(url)?;
+        let inbox = std::rc::Rc::new(std::cell::RefCell::new(VecDeque::new()));
+        let sink = inbox.clone();
+        let on_message = wasm_bindgen::closure::Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |event: web_sys::MessageEvent| {
+            if let Some(text) = event.data().as_string() {
+                sink.borrow_mut().push_back(text);
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        Ok(Self { socket, inbox, _on_message: on_message })
+    }
+
+    // Anything sent before the socket opens is dropped, callers retry what matters
+    pub fn send_text(&self, text: &str) {
+        if self.socket.ready_state() == web_sys::WebSocket::OPEN {
+            let _ = self.socket.send_with_str(text);
+        }
+    }
+
+    pub fn drain(&self) -> Vec<String> {
+        self.inbox.borrow_mut().drain(..).collect()
+    }
+}
+
+// The browser can't do UDP, the web build goes through the server's WebSocket port
+#[cfg(target_arch = "wasm32")]
+pub struct ClientTransport {
+    channel: WebSocketChannel,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl ClientTransport {
+    pub fn connect(url: &str) -> Result<Self, wasm_bindgen::JsValue> {
+        Ok(Self { channel: WebSocketChannel::open(url)? })
+    }
+
+    fn send(&self, message: &ClientMessage) {
+        if let Ok(json) = serde_json::to_string(message) {
+            self.channel.send_text(&json);
+        }
+    }
+
+    fn receive(&mut self) -> Vec<ServerMessage> {
+        self.channel.drain().into_iter()
+            .filter_map(|text| serde_json::from_str(&text).ok())
+            .collect()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct NetClient {
+    pub player_id: Option<u32>,
+    seq: u32,
+    // Last input the server confirmed, older snapshots are ignored
+    acked: u32,
+    // How the local kart moved after each input not yet confirmed, reapplied on top of every snapshot
+    history: VecDeque<PredictedStep>,
+    // Local kart at the end of the previous frame
+    last_pose: Option<(Vec3, Quat)>,
+    join_timer: f32,
+}
+
+// Pose change over one predicted frame, in the kart's own frame so it still applies after a correction turns the kart.
+// The inputs aren't re-simulated (the kart controller needs the whole physics world), so a wall bounce or
+// collision the server corrected gets the locally predicted motion added on top until the next snapshot.
+#[derive(Clone, Copy)]
+struct PredictedStep {
+    seq: u32,
+    moved: Vec3,
+    turned: Quat,
+}
+
+// Banana or shell simulated on the server, only drawn here
+#[derive(Component)]
+pub struct ReplicatedItem {
+    pub id: u64,
+}
+
+// A kart simulated on the server and only displayed here
+#[derive(Component)]
+pub struct RemoteKart {
+    pub player_id: u32,
+    buffer: VecDeque<(f32, Vec3, Quat)>,
+}
+
+// Client side of online races. With no address the client idles until `connect_to_server` is called (e.g. by the lobby).
+pub struct NetClientPlugin {
+    pub addr: Option<String>,
+}
+
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(addr) = &self.addr {
+            let transport = ClientTransport::connect(addr)
+                .unwrap_or_else(|err| panic!("Can't reach server {}: {:?}", addr, err));
+            app.insert_non_send_resource(transport)
+               .insert_state(RacePhase::Lobby);
+        }
+
+        app.init_resource::<NetClient>()
+           // Boxes, coins and hits are the server's call
+           .configure_sets(Update, ItemRules.run_if(|transport: Option<NonSend<ClientTransport>>| transport.is_none()))
+           .add_systems(PreUpdate, (client_receive, client_send_input).chain()
+               .after(crate::input::gather_input)
+               .run_if(|transport: Option<NonSend<ClientTransport>>| transport.is_some()))
+           .add_systems(Update, interpolate_remote_karts)
+           .add_systems(PostUpdate, record_prediction.after(PhysicsSet::Writeback))
+           .add_systems(Last, client_leave);
+    }
+}
+
+pub fn connect_to_server(commands: &mut Commands, addr: String) {
+    commands.queue(move |world: &mut World| {
+        match ClientTransport::connect(&addr) {
+            Ok(transport) => world.insert_non_send_resource(transport),
+            Err(err) => error!("Can't reach server {}: {:?}", addr, err),
+        }
+    });
+}
+
+fn client_receive(
+    mut commands: Commands,
+    mut transport: NonSendMut<ClientTransport>,
+    mut client: ResMut<NetClient>,
+    mut countdown: ResMut<Countdown>,
+    phase: Res<State<RacePhase>>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+    mut local_query: Query<(&mut Transform, &mut Velocity, &mut PlayerStats, &mut HeldItem), (With<LocalPlayer>, Without<RemoteKart>)>,
+    mut remote_query: Query<(Entity, &mut RemoteKart, &mut PlayerStats, &mut HeldItem, &mut Kart), Without<LocalPlayer>>,
+    mut item_query: Query<(Entity, &ReplicatedItem, &mut Transform), (Without<LocalPlayer>, Without<RemoteKart>)>,
+    mut layout_query: Query<(Entity, &LayoutIndex, Has<Coin>, &mut Visibility), Or<(With<ItemBox>, With<Coin>)>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+) {
+    for message in transport.receive() {
+        match message {
+            ServerMessage::Welcome { player_id } => {
+                info!("Joined server as player {}", player_id);
+                client.player_id = Some(player_id);
+            }
+            ServerMessage::Rejected { reason } => {
+                error!("The server turned us away: {}", reason);
+                commands.queue(|world: &mut World| {
+                    world.remove_non_send_resource::<ClientTransport>();
+                });
+                return;
+            }
+            ServerMessage::Snapshot { phase: server_phase, countdown: remaining, karts, items, boxes, coins, .. } => {
+                let now = time.elapsed_secs();
+
+                // The server owns the start of the race
+                countdown.remaining = remaining;
+                if *phase.get() != server_phase {
+                    next_phase.set(server_phase);
+                }
+                let mut spawned = Vec::new();
+
+                for state in &karts {
+                    if Some(state.player_id) == client.player_id {
+                        let Ok((mut transform, mut velocity, mut stats, mut held)) = local_query.get_single_mut() else { continue; };
+                        reconcile(&mut client, state, &mut transform, &mut velocity);
+                        apply_stats(state, &mut stats, &mut held);
+                        continue;
+                    }
+
+                    if let Some((_, mut remote, mut stats, mut held, mut kart)) = remote_query.iter_mut().find(|(_, r, ..)| r.player_id == state.player_id) {
+                        remote.buffer.push_back((now, state.position(), state.rotation()));
+                        apply_stats(state, &mut stats, &mut held);
+                        kart.is_boosting = state.boosting;
+                    } else if !spawned.contains(&state.player_id) {
+                        spawned.push(state.player_id);
+                        let kart = spawn_kart(&mut commands, &asset_server, state.position(), state.rotation());
+                        commands.entity(kart).insert((
+                            // Moved by interpolation only, the local physics must not fight it
+                            RigidBody::KinematicPositionBased,
+                            RemoteKart {
+                                player_id: state.player_id,
+                                buffer: VecDeque::from([(now, state.position(), state.rotation())]),
+                            },
+                        ));
+                    }
+                }
+
+                // Whoever is missing from the snapshot left the race
+                for (entity, remote, ..) in remote_query.iter() {
+                    if !karts.iter().any(|state| state.player_id == remote.player_id) {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+
+                sync_items(&mut commands, &asset_server, &items, &mut item_query);
+
+                // Taken coins are gone for good, broken boxes may come back
+                for (entity, index, is_coin, mut visibility) in layout_query.iter_mut() {
+                    let present = if is_coin { coins.get(index.0) } else { boxes.get(index.0) };
+                    match present {
+                        Some(false) if is_coin => commands.entity(entity).despawn_recursive(),
+                        Some(false) => *visibility = Visibility::Hidden,
+                        Some(true) => *visibility = Visibility::Inherited,
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Bananas and shells follow the server: new ones appear, known ones move, missing ones are gone
+fn sync_items(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    items: &[ItemState],
+    item_query: &mut Query<(Entity, &ReplicatedItem, &mut Transform), (Without<LocalPlayer>, Without<RemoteKart>)>,
+) {
+    for (entity, replicated, mut transform) in item_query.iter_mut() {
+        match items.iter().find(|state| state.id == replicated.id) {
+            Some(state) => transform.translation = Vec3::from_array(state.position),
+            None => commands.entity(entity).despawn_recursive(),
+        }
+    }
+
+    for state in items {
+        if item_query.iter().any(|(_, replicated, _)| replicated.id == state.id) {
+            continue;
+        }
+        commands.spawn((
+            Transform::from_translation(Vec3::from_array(state.position)),
+            Visibility::default(),
+            ReplicatedItem { id: state.id },
+            TrackEntity,
+        )).with_children(|parent| {
+            parent.spawn((
+                SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(state.item.model()))),
+                Transform::from_scale(Vec3::splat(0.01)),
+            ));
+        });
+    }
+}
+
+// Start from the server's state for the last input it applied, then add back the pose changes predicted since
+fn reconcile(client: &mut NetClient, state: &KartState, transform: &mut Transform, velocity: &mut Velocity) {
+    // UDP can deliver an older snapshot after a newer one
+    if state.last_input_seq < client.acked {
+        return;
+    }
+    client.acked = state.last_input_seq;
+    while client.history.front().is_some_and(|step| step.seq <= state.last_input_seq) {
+        client.history.pop_front();
+    }
+
+    let mut translation = state.position();
+    let mut rotation = state.rotation();
+    for step in &client.history {
+        translation += rotation * step.moved;
+        rotation = (rotation * step.turned).normalize();
+    }
+
+    transform.translation = translation;
+    transform.rotation = rotation;
+    // The server's velocity, turned as much as the predicted steps turned the kart
+    velocity.linvel = rotation * state.rotation().inverse() * Vec3::from_array(state.linvel);
+    client.last_pose = Some((translation, rotation));
+}
+
+fn apply_stats(state: &KartState, stats: &mut PlayerStats, held: &mut HeldItem) {
+    stats.current_lap = state.lap;
+    stats.last_checkpoint = state.last_checkpoint;
+    stats.coin_count = state.coins;
+    if held.item != state.item {
+        held.item = state.item;
+        held.uses = state.item.map_or(0, |item| item.uses());
+    }
+}
+
+fn client_send_input(
+    transport: NonSend<ClientTransport>,
+    mut client: ResMut<NetClient>,
+    local_query: Query<&KartInput, With<LocalPlayer>>,
+    time: Res<Time>,
+) {
+    if client.player_id.is_none() {
+        client.join_timer -= time.delta_secs();
+        if client.join_timer <= 0.0 {
+            transport.send(&ClientMessage::Join);
+            client.join_timer = 0.5;
+        }
+        return;
+    }
+
+    if let Ok(input) = local_query.get_single() {
+        client.seq += 1;
+        transport.send(&ClientMessage::Input { seq: client.seq, input: *input });
+    }
+}
+
+fn client_leave(transport: Option<NonSend<ClientTransport>>, mut exit_events: EventReader<AppExit>) {
+    if let (Some(transport), Some(_)) = (transport, exit_events.read().next()) {
+        transport.send(&ClientMessage::Leave);
+    }
+}
+
+// What this frame's input did to the local kart, kept until the server confirms it
+fn record_prediction(
+    mut client: ResMut<NetClient>,
+    local_query: Query<&Transform, With<LocalPlayer>>,
+) {
+    if client.player_id.is_none() {
+        return;
+    }
+    let Ok(transform) = local_query.get_single() else { return; };
+    let pose = (transform.translation, transform.rotation);
+
+    if let Some((last_translation, last_rotation)) = client.last_pose {
+        let step = PredictedStep {
+            seq: client.seq,
+            moved: last_rotation.inverse() * (pose.0 - last_translation),
+            turned: last_rotation.inverse() * pose.1,
+        };
+        client.history.push_back(step);
+        // Don't grow forever if the server stops answering
+        while client.history.len() > MAX_HISTORY {
+            client.history.pop_front();
+        }
+    }
+    client.last_pose = Some(pose);
+}
+
+fn interpolate_remote_karts(
+    mut query: Query<(&mut Transform, &mut RemoteKart)>,
+    time: Res<Time>,
+) {
+    let render_time = time.elapsed_secs() - INTERPOLATION_DELAY;
+
+    for (mut transform, mut remote) in query.iter_mut() {
+        // Keep a single snapshot older than the render time to blend from
+        while remote.buffer.len() > 2 && remote.buffer[1].0 <= render_time {
+            remote.buffer.pop_front();
+        }
+
+        match (remote.buffer.front(), remote.buffer.get(1)) {
+            (Some(&(t0, p0, r0)), Some(&(t1, p1, r1))) => {
+                let t = if t1 > t0 { ((render_time - t0) / (t1 - t0)).clamp(0.0, 1.0) } else { 1.0 };
+                transform.translation = p0.lerp(p1, t);
+                transform.rotation = r0.slerp(r1, t);
+            }
+            (Some(&(_, p0, r0)), None) => {
+                transform.translation = p0;
+                transform.rotation = r0;
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,13 +1,46 @@
 use bevy::prelude::*;
 use bevy::gltf::GltfAssetLabel;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
 use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+use crate::input::{KartInput, PlayerController};
+use crate::items::KartHit;
+use crate::logic::{RaceConfig, RacePhase};
+use crate::track::{is_kill_volume, surface_of, KillVolume, Surface, SurfaceKind, TrackDefinition, TrackSpline};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_player)
-           .add_systems(Update, (player_input, player_physics, player_reset, camera_follow));
+        app.init_resource::<LocalPlayers>()
+           .init_resource::<KartSelections>()
+           .insert_resource(crate::config::load::<KartCatalog>("config/karts.json", include_str!("../assets/config/karts.json")))
+           .add_event::<KartLanded>()
+           .add_event::<WallHit>()
+           .add_event::<KartHit>()
+           .add_systems(Startup, spawn_player)
+           .add_systems(Update, (
+               apply_kart_build,
+               apply_kart_weight,
+               player_input,
+               player_physics,
+               wall_bounce,
+               bump_karts,
+               (player_reset, respawn_karts).chain(),
+               camera_follow,
+               update_viewports,
+           ));
+    }
+}
+
+// How many people play on this machine (split-screen)
+#[derive(Resource)]
+pub struct LocalPlayers(pub usize);
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        Self(1)
     }
 }
 
@@ -20,31 +53,31 @@ pub struct Kart {
     pub is_boosting: bool,
     pub boost_timer: f32,
     pub jump_cooldown: f32,
+    pub spinout_timer: f32,
+    pub star_timer: f32,
+    // At least one wheel on the ground, and the averaged normal under the wheels
+    pub grounded: bool,
+    pub ground_normal: Vec3,
+    // What the wheels are on, Road while airborne
+    pub surface: SurfaceKind,
+    // Left the ground off a ramp, tricks allowed until landing
+    pub trick_ready: bool,
+    // Seconds since the wheels left the ground
+    pub airtime: f32,
+    // Trick done this jump, and what's left of its animation
+    pub tricked: bool,
+    pub trick_timer: f32,
+    // Jump button state last frame, tricks need a fresh press
+    pub jump_held: bool,
+    // Time before another wall hit shakes the camera and plays the bump
+    pub wall_cooldown: f32,
     pub last_safe_pos: Vec3,
     pub last_safe_rot: Quat,
 }
 
-#[derive(Component)]
-pub struct KartVisual;
-
-#[derive(Component)]
-pub struct FollowCamera;
-
-fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let start_pos = Vec3::new(0.0, 2.0, 0.0);
-    let start_rot = Quat::IDENTITY;
-
-    // Main Physics Body
-    commands.spawn((
-        Transform::from_translation(start_pos).with_rotation(start_rot),
-        RigidBody::Dynamic,
-        Collider::ball(0.5),
-        Damping { linear_damping: 0.5, angular_damping: 0.5 },
-        ExternalImpulse::default(),
-        Velocity::default(),
-        Sleeping::disabled(),
-        ActiveEvents::COLLISION_EVENTS,
-        Kart {
+impl Kart {
+    pub fn new(start_pos: Vec3, start_rot: Quat) -> Self {
+        Self {
             speed: 0.0,
             steering: 0.0,
             drift_dir: 0.0,
@@ -52,166 +85,861 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
             is_boosting: false,
             boost_timer: 0.0,
             jump_cooldown: 0.0,
+            spinout_timer: 0.0,
+            star_timer: 0.0,
+            grounded: false,
+            ground_normal: Vec3::Y,
+            surface: SurfaceKind::Road,
+            trick_ready: false,
+            airtime: 0.0,
+            tricked: false,
+            trick_timer: 0.0,
+            jump_held: false,
+            wall_cooldown: 0.0,
             last_safe_pos: start_pos,
             last_safe_rot: start_rot,
-        },
-        crate::logic::PlayerStats {
-            current_lap: 1,
-            last_checkpoint: -1,
-            coin_count: 0,
-        },
+        }
+    }
+}
+
+// Kart slammed into track geometry, strength 0..1
+#[derive(Event)]
+pub struct WallHit {
+    pub kart: Entity,
+    pub strength: f32,
+}
+
+// Back on the ground after a real jump (small bumps don't count)
+#[derive(Event)]
+pub struct KartLanded {
+    pub kart: Entity,
+    pub airtime: f32,
+    pub trick: bool,
+}
+
+// Lakitu recovery in progress: fade out, put back on the racing line, hold, drop. No control until it's over.
+#[derive(Component, Default)]
+pub struct Respawn {
+    pub elapsed: f32,
+    pub placed: bool,
+}
+
+impl Respawn {
+    // 0 = clear, 1 = black
+    pub fn fade(&self) -> f32 {
+        if self.elapsed < RESPAWN_FADE_OUT {
+            self.elapsed / RESPAWN_FADE_OUT
+        } else {
+            (1.0 - (self.elapsed - RESPAWN_FADE_OUT) / RESPAWN_HOLD).clamp(0.0, 1.0)
+        }
+    }
+}
+
+pub const RESPAWN_FADE_OUT: f32 = 0.5;
+// Hanging from the fishing rod above the track
+pub const RESPAWN_HOLD: f32 = 0.7;
+pub const RESPAWN_TIME: f32 = 1.6;
+// Added to the kart's race time, on top of the time lost
+pub const RESPAWN_PENALTY: f32 = 1.0;
+// Put back this far behind where the kart left the track
+const RESPAWN_BACKTRACK: f32 = 2.0;
+const RESPAWN_DROP_HEIGHT: f32 = 2.0;
+
+#[derive(Component)]
+pub struct KartVisual {
+    // Smoothed tilt onto the ground, relative to the chassis
+    pub surface_tilt: Quat,
+}
+
+// Mass of a weight 1.0 kart
+pub const KART_MASS: f32 = 1.0;
+
+// How a kart drives, the sum of its character, kart body and wheels (see KartCatalog)
+#[derive(Component, Clone, Debug, Deserialize)]
+pub struct KartStats {
+    // Top speed, and top speed while boosting
+    pub speed: f32,
+    pub boost_speed: f32,
+    // How hard the engine pulls towards top speed
+    pub acceleration: f32,
+    // Heavier karts weigh more in bumps and get pushed around less
+    pub weight: f32,
+    // Turn rates, normal and drifting
+    pub handling: f32,
+    pub drift_handling: f32,
+    // Multiplier on tyre grip
+    pub traction: f32,
+}
+
+impl Default for KartStats {
+    fn default() -> Self {
+        Self {
+            speed: 38.0,
+            boost_speed: 65.0,
+            acceleration: 4.5,
+            weight: 1.0,
+            handling: 2.8,
+            drift_handling: 3.8,
+            traction: 1.0,
+        }
+    }
+}
+
+impl KartStats {
+    pub fn mass(&self) -> f32 {
+        KART_MASS * self.weight
+    }
+
+    fn add(&mut self, part: &KartPart) {
+        self.speed += part.speed;
+        self.boost_speed += part.boost_speed;
+        self.acceleration += part.acceleration;
+        self.weight += part.weight;
+        self.handling += part.handling;
+        self.drift_handling += part.drift_handling;
+        self.traction += part.traction;
+    }
+}
+
+// A character, kart body or set of wheels: what it adds to the base stats, and its model if it has one
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct KartPart {
+    pub name: String,
+    pub model: Option<String>,
+    pub speed: f32,
+    pub boost_speed: f32,
+    pub acceleration: f32,
+    pub weight: f32,
+    pub handling: f32,
+    pub drift_handling: f32,
+    pub traction: f32,
+}
+
+// Used when nothing in the build has a model of its own
+pub const DEFAULT_KART_MODEL: &str = "models/characters/mariokarttest.glb";
+
+// Every character, kart body and wheel set, from config/karts.json, in menu order
+#[derive(Resource, Deserialize)]
+pub struct KartCatalog {
+    pub base: KartStats,
+    pub characters: Vec<KartPart>,
+    pub karts: Vec<KartPart>,
+    pub wheels: Vec<KartPart>,
+}
+
+fn find_part<'a>(parts: &'a [KartPart], name: &str) -> Option<&'a KartPart> {
+    parts.iter().find(|part| part.name == name)
+}
+
+impl KartCatalog {
+    fn parts(&self, build: &KartBuild) -> impl Iterator<Item = &KartPart> {
+        [
+            find_part(&self.characters, &build.character),
+            find_part(&self.karts, &build.kart),
+            find_part(&self.wheels, &build.wheels),
+        ].into_iter().flatten()
+    }
+
+    // Unknown parts add nothing
+    pub fn stats(&self, build: &KartBuild) -> KartStats {
+        let mut stats = self.base.clone();
+        for part in self.parts(build) {
+            stats.add(part);
+        }
+        // No part combination should make a kart weightless or undriveable
+        stats.weight = stats.weight.max(0.2);
+        stats.acceleration = stats.acceleration.max(0.5);
+        stats.traction = stats.traction.max(0.2);
+        stats
+    }
+
+    // The character's model wins over the kart body's
+    pub fn model(&self, build: &KartBuild) -> &str {
+        self.parts(build)
+            .find_map(|part| part.model.as_deref())
+            .unwrap_or(DEFAULT_KART_MODEL)
+    }
+}
+
+// Character, kart body and wheels a kart is built from
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct KartBuild {
+    pub character: String,
+    pub kart: String,
+    pub wheels: String,
+}
+
+impl Default for KartBuild {
+    fn default() -> Self {
+        Self {
+            character: "Mario".to_string(),
+            kart: "Standard".to_string(),
+            wheels: "Standard".to_string(),
+        }
+    }
+}
+
+// Builds picked on the selection screen, one per local player
+#[derive(Resource, Default, Clone)]
+pub struct KartSelections(pub Vec<KartBuild>);
+
+// Suspension points in kart space: front left, front right, back left, back right
+const WHEEL_ANCHORS: [Vec3; 4] = [
+    Vec3::new(-0.5, -0.1, -0.7),
+    Vec3::new(0.5, -0.1, -0.7),
+    Vec3::new(-0.5, -0.1, 0.7),
+    Vec3::new(0.5, -0.1, 0.7),
+];
+const SUSPENSION_REST: f32 = 0.35;
+const WHEEL_RADIUS: f32 = 0.2;
+// Per wheel, per unit of mass
+const SPRING_STIFFNESS: f32 = 40.0;
+const SPRING_DAMPING: f32 = 4.0;
+// How fast sideways velocity is killed: planted, sliding, drifting
+const GRIP: f32 = 10.0;
+const SLIDE_GRIP: f32 = 3.0;
+const DRIFT_GRIP: f32 = 4.0;
+// Airborne karts slowly level out
+const AIR_UPRIGHT: f32 = 8.0;
+// How quickly the model follows the ground under it
+const VISUAL_ALIGN_RATE: f32 = 10.0;
+// Steeper than this isn't a place to respawn on
+const SAFE_GROUND_MIN_UP: f32 = 0.8;
+// Top speed on grass and sand, unless boosting
+const OFFROAD_SPEED: f32 = 0.55;
+const BOOST_PAD_TIME: f32 = 1.0;
+const RAMP_LAUNCH: f32 = 6.0;
+// Tricks: press jump this soon after leaving a ramp, spin for TRICK_TIME, boost on landing
+const TRICK_WINDOW: f32 = 0.5;
+const TRICK_TIME: f32 = 0.5;
+const TRICK_BOOST_TIME: f32 = 0.8;
+const LANDING_MIN_AIRTIME: f32 = 0.25;
+// Walls: how much of the impact speed comes back, and how much speed a head-on hit costs
+const WALL_BOUNCE: f32 = 0.35;
+const HEAD_ON_PENALTY: f32 = 0.5;
+const WALL_MIN_IMPACT: f32 = 2.0;
+// Impact speed for a full-strength hit
+const WALL_MAX_IMPACT: f32 = 20.0;
+const WALL_HIT_COOLDOWN: f32 = 0.3;
+
+const KART_GROUP: Group = Group::GROUP_2;
+// Kart against kart: contact distance, how hard they shove apart, and the shove when barely moving
+const BUMP_RADIUS: f32 = 0.8;
+const BUMP_STRENGTH: f32 = 1.2;
+const BUMP_MIN_SHOVE: f32 = 1.5;
+// A boosting kart rams this much harder
+const RAM_MULTIPLIER: f32 = 2.0;
+
+// Full grip until the tyres start sliding, then it falls off
+fn grip_curve(slip: f32) -> f32 {
+    let t = ((slip - 0.15) / 0.35).clamp(0.0, 1.0);
+    GRIP + (SLIDE_GRIP - GRIP) * t
+}
+
+// A kart driven from this machine: HUD, camera and audio follow it
+#[derive(Component)]
+pub struct LocalPlayer {
+    pub index: usize,
+}
+
+#[derive(Component)]
+pub struct FollowCamera {
+    pub target: Entity,
+    pub player: usize,
+    // Shake left from wall hits, decays over time
+    pub shake: f32,
+}
+
+// Slot on the track's grid, already laid out for its variant (see TrackDefinition::with_variant)
+pub fn grid_transform(index: usize, track: &TrackDefinition) -> (Vec3, Quat) {
+    track.grid.slot(index)
+}
+
+fn spawn_player(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    local_players: Res<LocalPlayers>,
+    selections: Res<KartSelections>,
+    track: Res<TrackDefinition>,
+) {
+    for index in 0..local_players.0 {
+        let (start_pos, start_rot) = grid_transform(index, &track);
+
+        let kart = spawn_kart(&mut commands, &asset_server, start_pos, start_rot);
+        commands.entity(kart).insert((
+            LocalPlayer { index },
+            PlayerController::for_player(index, local_players.0),
+        ));
+        commands.entity(kart).insert(selections.0.get(index).cloned().unwrap_or_default());
+
+        // Camera initial target (Exactly at Mario's position as requested)
+        let cam_start_pos = start_pos;
+
+        // Camera, each one renders into its own viewport (see update_viewports)
+        commands.spawn((
+            Camera3d::default(),
+            Camera {
+                order: index as isize,
+                // The first camera already cleared the whole window
+                clear_color: if index > 0 { ClearColorConfig::None } else { ClearColorConfig::Default },
+                ..default()
+            },
+            Transform::from_translation(cam_start_pos).looking_at(start_pos + Vec3::NEG_Z, Vec3::Y),
+            FollowCamera { target: kart, player: index, shake: 0.0 },
+        ));
+    }
+}
+
+pub fn spawn_kart(commands: &mut Commands, asset_server: &AssetServer, start_pos: Vec3, start_rot: Quat) -> Entity {
+    // Main Physics Body
+    commands.spawn((
+        Transform::from_translation(start_pos).with_rotation(start_rot),
+        KartBuild::default(),
+        KartStats::default(),
+        // Physics, nested to stay under Bevy's bundle size limit
+        (
+            RigidBody::Dynamic,
+            // Chassis only, the wheels are suspension rays (see player_physics)
+            Collider::cuboid(0.55, 0.2, 0.8),
+            ColliderMassProperties::Mass(KART_MASS),
+            // Karts don't push each other through the solver, bump_karts does it by weight
+            SolverGroups::new(KART_GROUP, Group::ALL ^ KART_GROUP),
+            Damping { linear_damping: 0.3, angular_damping: 1.0 },
+            ExternalForce::default(),
+            ExternalImpulse::default(),
+            Velocity::default(),
+            Sleeping::disabled(),
+            ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS,
+            // Resting on the chassis or scraping a wall stays under this
+            ContactForceEventThreshold(30.0),
+        ),
+        // Race state
+        (
+            Kart::new(start_pos, start_rot),
+            crate::logic::PlayerStats::default(),
+            crate::logic::RacePosition(1),
+            crate::items::HeldItem::default(),
+            KartInput::default(),
+        ),
     )).with_children(|parent| {
         // Visual Model
         parent.spawn((
-            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/characters/mariokarttest.glb"))),
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(DEFAULT_KART_MODEL))),
             Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::PI))
                 .with_scale(Vec3::splat(0.01)),
-            KartVisual,
+            KartVisual { surface_tilt: Quat::IDENTITY },
         ));
-    });
-
-    // Camera initial target (Exactly at Mario's position as requested)
-    let cam_start_pos = start_pos; 
-
-    // Camera
-    commands.spawn((
-        Camera3d::default(),
-        Transform::from_translation(cam_start_pos).looking_at(start_pos + Vec3::NEG_Z, Vec3::Y),
-        FollowCamera,
-    ));
+    }).id()
 }
 
 fn player_input(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut Kart, &mut ExternalImpulse, &mut Velocity, &Transform)>,
+    mut query: Query<(&mut Kart, &mut ExternalImpulse, &KartInput, &KartStats, Has<Respawn>)>,
+    phase: Res<State<RacePhase>>,
+    config: Res<RaceConfig>,
     time: Res<Time>,
 ) {
     let dt = time.delta_secs();
-    for (mut kart, mut impulse, _velocity, _transform) in query.iter_mut() {
+    // Engines idle on the grid until the countdown ends
+    let racing = *phase.get() == RacePhase::Racing;
+    for (mut kart, mut impulse, input, stats, respawning) in query.iter_mut() {
         if kart.jump_cooldown > 0.0 { kart.jump_cooldown -= dt; }
+        if kart.star_timer > 0.0 { kart.star_timer -= dt; }
+        if kart.trick_timer > 0.0 { kart.trick_timer -= dt; }
+        if kart.wall_cooldown > 0.0 { kart.wall_cooldown -= dt; }
+        let jump_pressed = input.jump && !kart.jump_held;
+        kart.jump_held = input.jump;
+        if kart.spinout_timer > 0.0 || respawning {
+            // No control while spinning out from a hit or being put back on the track
+            kart.spinout_timer -= dt;
+            kart.speed = 0.0;
+            kart.steering = 0.0;
+            kart.drift_dir = 0.0;
+            kart.drift_power = 0.0;
+            continue;
+        }
 
-        let up = keyboard.pressed(KeyCode::ArrowUp) || keyboard.pressed(KeyCode::KeyW) || keyboard.pressed(KeyCode::KeyZ);
-        let down = keyboard.pressed(KeyCode::ArrowDown) || keyboard.pressed(KeyCode::KeyS);
-        let left = keyboard.pressed(KeyCode::ArrowLeft) || keyboard.pressed(KeyCode::KeyA) || keyboard.pressed(KeyCode::KeyQ);
-        let right = keyboard.pressed(KeyCode::ArrowRight) || keyboard.pressed(KeyCode::KeyD);
-        let jump = keyboard.pressed(KeyCode::KeyV) || keyboard.pressed(KeyCode::Space);
+        // Trick off a ramp
+        if racing && jump_pressed && kart.trick_ready && !kart.grounded && !kart.tricked && kart.airtime < TRICK_WINDOW {
+            kart.tricked = true;
+            kart.trick_timer = TRICK_TIME;
+        }
 
-        let mut steer = 0.0;
-        if left { steer += 1.0; }
-        if right { steer -= 1.0; }
+        let steer = input.steer;
+        // Hops need something to push off
+        let jump = racing && input.jump && kart.grounded;
         kart.steering = steer;
 
-        let mut acc = 0.0;
-        if up { acc += 1.0; }
-        if down { acc -= 1.0; }
-        
-        let max_speed = if kart.is_boosting { 65.0 } else { 38.0 };
+        let acc = if racing { input.throttle } else { 0.0 };
+
+        let mut max_speed = (if kart.is_boosting { stats.boost_speed } else { stats.speed }) * config.cc.speed_multiplier();
+        if kart.surface == SurfaceKind::Offroad && !kart.is_boosting {
+            max_speed *= OFFROAD_SPEED;
+        }
         kart.speed = acc * max_speed;
 
         if jump && kart.jump_cooldown <= 0.0 {
-            impulse.impulse += Vec3::Y * 4.5;
+            impulse.impulse += Vec3::Y * 4.5 * stats.mass();
             kart.jump_cooldown = 0.7;
             if steer != 0.0 {
                 kart.drift_dir = steer.signum();
             }
         }
 
-        if !jump {
-            if kart.drift_dir != 0.0 {
-                if kart.drift_power > 0.8 {
-                    kart.is_boosting = true;
-                    kart.boost_timer = 1.2;
-                }
-                kart.drift_dir = 0.0;
-                kart.drift_power = 0.0;
+        if !input.jump && kart.drift_dir != 0.0 {
+            if kart.drift_power > 0.8 {
+                kart.is_boosting = true;
+                kart.boost_timer = 1.2;
             }
+            kart.drift_dir = 0.0;
+            kart.drift_power = 0.0;
         }
     }
 }
 
 fn player_physics(
-    mut query: Query<(&mut Transform, &mut Velocity, &mut Kart, &mut ExternalImpulse)>,
-    mut visual_query: Query<&mut Transform, (With<KartVisual>, Without<Kart>)>,
+    mut query: Query<(Entity, &Transform, &mut Velocity, &mut Kart, &KartStats, &mut ExternalForce, &mut ExternalImpulse, &Children)>,
+    mut visual_query: Query<(&mut Transform, &mut KartVisual), Without<Kart>>,
+    surface_query: Query<&Surface>,
+    parent_query: Query<&Parent>,
+    track: Res<TrackDefinition>,
+    rapier: ReadDefaultRapierContext,
+    mut landed_events: EventWriter<KartLanded>,
     time: Res<Time>,
 ) {
     let dt = time.delta_secs();
-    for (mut transform, mut velocity, mut kart, mut impulse) in query.iter_mut() {
+    let rapier = rapier.single();
+    for (entity, transform, mut velocity, mut kart, stats, mut external_force, mut impulse, children) in query.iter_mut() {
+        let mass = stats.mass();
         // Rotation logic
         let mut target_rotation_speed = if kart.drift_dir != 0.0 {
             kart.drift_power += dt;
-            kart.drift_dir * 3.8
+            kart.drift_dir * stats.drift_handling
         } else {
-            kart.steering * 2.8
+            kart.steering * stats.handling
         };
         
         // Reduce rotation if moving slow
         let speed_factor = (velocity.linvel.length() / 10.0).min(1.0);
         target_rotation_speed *= speed_factor;
-        
-        transform.rotate_y(target_rotation_speed * dt);
 
-        // Movement impulsions
-        let forward = transform.forward();
-        let current_forward_vel = velocity.linvel.dot(*forward);
-        
-        if kart.speed != 0.0 {
-            let force = (kart.speed - current_forward_vel) * 4.5;
-            impulse.impulse += forward * force * dt;
+        // Suspension: one ray per wheel, a spring/damper pushing the chassis up where it touches
+        let up = *transform.up();
+        let ray_length = SUSPENSION_REST + WHEEL_RADIUS;
+        let filter = QueryFilter::default().exclude_rigid_body(entity).exclude_sensors();
+        let mut force = Vec3::ZERO;
+        let mut torque = Vec3::ZERO;
+        let mut grounded_wheels = 0;
+        let mut normal_sum = Vec3::ZERO;
+        let mut surfaces = Vec::with_capacity(WHEEL_ANCHORS.len());
+
+        for anchor in WHEEL_ANCHORS {
+            let origin = transform.transform_point(anchor);
+            let Some((hit_entity, hit)) = rapier.cast_ray_and_get_normal(origin, -up, ray_length, true, filter) else { continue; };
+            grounded_wheels += 1;
+            normal_sum += hit.normal;
+            // Tagged geometry first, then the track definition's regions
+            surfaces.push(surface_of(hit_entity, &surface_query, &parent_query)
+                .or_else(|| track.surface_at(hit.point))
+                .unwrap_or_default());
+
+            let compression = ray_length - hit.time_of_impact;
+            let arm = origin - transform.translation;
+            let point_velocity = velocity.linvel + velocity.angvel.cross(arm);
+            let spring = (compression * SPRING_STIFFNESS - point_velocity.dot(up) * SPRING_DAMPING).max(0.0) * mass;
+            force += up * spring;
+            torque += arm.cross(up * spring);
         }
 
+        let was_grounded = kart.grounded;
+        kart.grounded = grounded_wheels > 0;
+        if kart.grounded {
+            if !was_grounded && kart.airtime > LANDING_MIN_AIRTIME {
+                if kart.tricked {
+                    kart.is_boosting = true;
+                    kart.boost_timer = kart.boost_timer.max(TRICK_BOOST_TIME);
+                }
+                landed_events.send(KartLanded { kart: entity, airtime: kart.airtime, trick: kart.tricked });
+            }
+            kart.airtime = 0.0;
+            kart.tricked = false;
+        } else {
+            kart.airtime += dt;
+        }
+        kart.ground_normal = if kart.grounded { normal_sum.normalize_or(Vec3::Y) } else { Vec3::Y };
+
+        // A single wheel is enough to hit a pad, off-road needs most of the kart on it
+        let on = |kind: SurfaceKind| surfaces.iter().filter(|s| **s == kind).count();
+        let previous_surface = kart.surface;
+        kart.surface = if on(SurfaceKind::BoostPad) > 0 {
+            SurfaceKind::BoostPad
+        } else if on(SurfaceKind::Ramp) > 0 {
+            SurfaceKind::Ramp
+        } else if on(SurfaceKind::Offroad) * 2 > surfaces.len() {
+            SurfaceKind::Offroad
+        } else {
+            SurfaceKind::Road
+        };
+
+        match kart.surface {
+            SurfaceKind::BoostPad => {
+                kart.is_boosting = true;
+                kart.boost_timer = kart.boost_timer.max(BOOST_PAD_TIME);
+            }
+            SurfaceKind::Ramp => {
+                if previous_surface != SurfaceKind::Ramp {
+                    impulse.impulse += kart.ground_normal * RAMP_LAUNCH * mass;
+                }
+                kart.trick_ready = true;
+            }
+            // Landed back on the track
+            SurfaceKind::Road | SurfaceKind::Offroad if kart.grounded => kart.trick_ready = false,
+            _ => {}
+        }
+
+        if kart.grounded {
+            let ground_normal = kart.ground_normal;
+            let traction = grounded_wheels as f32 / WHEEL_ANCHORS.len() as f32;
+            let forward = transform.forward().reject_from_normalized(ground_normal).normalize_or_zero();
+            let right = forward.cross(ground_normal);
+
+            // Movement, along the ground
+            if kart.speed != 0.0 {
+                let current_forward_vel = velocity.linvel.dot(forward);
+                force += forward * (kart.speed - current_forward_vel) * stats.acceleration * mass * traction;
+            }
+
+            // Lateral grip
+            let lateral_vel = velocity.linvel.dot(right);
+            let slip = lateral_vel.abs() / velocity.linvel.length().max(1.0);
+            let grip = if kart.drift_dir != 0.0 { DRIFT_GRIP } else { grip_curve(slip) };
+            force -= right * lateral_vel * grip * stats.traction * mass * traction;
+        } else {
+            torque += up.cross(Vec3::Y) * AIR_UPRIGHT * mass;
+        }
+
+        external_force.force = force;
+        external_force.torque = torque;
+
+        // Steering drives the yaw rate around the kart's own up axis, pitch and roll are left to the suspension
+        let yaw_rate = velocity.angvel.dot(up);
+        velocity.angvel += up * (target_rotation_speed - yaw_rate);
+
         // Boost
         if kart.is_boosting {
             kart.boost_timer -= dt;
-            impulse.impulse += forward * 50.0 * dt;
+            impulse.impulse += transform.forward() * 50.0 * mass * dt;
             if kart.boost_timer <= 0.0 { kart.is_boosting = false; }
         }
 
-        // Stabilize angular velocity
-        velocity.angvel.x = 0.0;
-        velocity.angvel.z = 0.0;
-
-        // Visual tilt & drift angle
-        if let Some(mut visual_transform) = visual_query.iter_mut().next() {
+        // Visual tilt & drift angle, on top of the surface alignment
+        let visual_child = children.iter().copied().find(|child| visual_query.contains(*child));
+        if let Some(Ok((mut visual_transform, mut visual))) = visual_child.map(|child| visual_query.get_mut(child)) {
             let drift_tilt = kart.drift_dir * 0.2;
             let steer_tilt = kart.steering * 0.1;
-            visual_transform.rotation = Quat::from_rotation_y(std::f32::consts::PI + drift_tilt) * Quat::from_rotation_z(steer_tilt);
+            // Two full turns over the spinout
+            let spin = kart.spinout_timer.max(0.0) / 1.2 * std::f32::consts::TAU * 2.0;
+
+            // The chassis lags behind bumps on its springs, the model settles flush with the ground
+            let target_tilt = if kart.grounded {
+                Quat::from_rotation_arc(Vec3::Y, transform.rotation.inverse() * kart.ground_normal)
+            } else {
+                Quat::IDENTITY
+            };
+            visual.surface_tilt = visual.surface_tilt.slerp(target_tilt, (VISUAL_ALIGN_RATE * dt).min(1.0));
+
+            // Trick: one barrel roll
+            let roll = kart.trick_timer.max(0.0) / TRICK_TIME * std::f32::consts::TAU;
+
+            visual_transform.rotation = visual.surface_tilt * Quat::from_rotation_y(std::f32::consts::PI + drift_tilt + spin) * Quat::from_rotation_z(steer_tilt + roll);
         }
-        
-        // Update safe position (simplified: if grounded)
-        if transform.translation.y > 0.0 && transform.translation.y < 5.0 {
+
+        // Respawn point: all wheels down on reasonably flat ground
+        if grounded_wheels == WHEEL_ANCHORS.len() && kart.ground_normal.y > SAFE_GROUND_MIN_UP {
              kart.last_safe_pos = transform.translation;
              kart.last_safe_rot = transform.rotation;
         }
     }
 }
 
+// The solver already stopped the kart against the wall: bounce it back out with part of the impact speed,
+// keep most of the speed on glancing hits and lose some on head-on ones
+fn wall_bounce(
+    mut contact_events: EventReader<ContactForceEvent>,
+    mut kart_query: Query<(&Transform, &mut Velocity, &mut Kart, &KartStats)>,
+    sensor_query: Query<(), With<Sensor>>,
+    rapier: ReadDefaultRapierContext,
+    mut wall_events: EventWriter<WallHit>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    if dt <= 0.0 {
+        return;
+    }
+    let rapier = rapier.single();
+
+    for event in contact_events.read() {
+        // Kart against kart is bumping, not a wall
+        let (kart_entity, wall, force_on_kart) = match (kart_query.contains(event.collider1), kart_query.contains(event.collider2)) {
+            (true, false) => (event.collider1, event.collider2, -event.total_force),
+            (false, true) => (event.collider2, event.collider1, event.total_force),
+            _ => continue,
+        };
+        if sensor_query.contains(wall) {
+            continue;
+        }
+
+        // Only sideways pushes, landing on the chassis is the floor
+        let mut normal = Vec3::new(force_on_kart.x, 0.0, force_on_kart.z);
+        if normal.length() < force_on_kart.y.abs() {
+            continue;
+        }
+        normal = normal.normalize_or_zero();
+
+        let Ok((transform, mut velocity, mut kart, stats)) = kart_query.get_mut(kart_entity) else { continue; };
+
+        // Sanity check the direction: the wall must be behind the normal
+        let filter = QueryFilter::default().exclude_rigid_body(kart_entity).exclude_sensors();
+        if rapier.cast_ray(transform.translation, -normal, 2.0, true, filter).map(|(hit, _)| hit) != Some(wall) {
+            normal = -normal;
+        }
+
+        let impact = force_on_kart.length() * dt / stats.mass();
+        if impact < WALL_MIN_IMPACT {
+            continue;
+        }
+
+        let along = velocity.linvel - normal * velocity.linvel.dot(normal);
+        let head_on = impact / (impact + along.length());
+        velocity.linvel = along * (1.0 - HEAD_ON_PENALTY * head_on) + normal * impact * WALL_BOUNCE + Vec3::Y * velocity.linvel.y;
+        kart.drift_dir = 0.0;
+        kart.drift_power = 0.0;
+
+        if kart.wall_cooldown <= 0.0 {
+            kart.wall_cooldown = WALL_HIT_COOLDOWN;
+            wall_events.send(WallHit { kart: kart_entity, strength: (impact / WALL_MAX_IMPACT).min(1.0) });
+        }
+    }
+}
+
+// Stats and model follow the build, so swapping character or parts retunes and reskins the kart
+fn apply_kart_build(
+    mut query: Query<(&KartBuild, &mut KartStats, &Children), Changed<KartBuild>>,
+    mut visual_query: Query<&mut SceneRoot, With<KartVisual>>,
+    catalog: Res<KartCatalog>,
+    asset_server: Res<AssetServer>,
+) {
+    for (build, mut stats, children) in query.iter_mut() {
+        *stats = catalog.stats(build);
+
+        let model = asset_server.load(GltfAssetLabel::Scene(0).from_asset(catalog.model(build).to_string()));
+        for child in children.iter() {
+            if let Ok(mut scene) = visual_query.get_mut(*child) {
+                if scene.0 != model {
+                    scene.0 = model.clone();
+                }
+            }
+        }
+    }
+}
+
+fn apply_kart_weight(mut query: Query<(&KartStats, &mut ColliderMassProperties), Changed<KartStats>>) {
+    for (stats, mut mass) in query.iter_mut() {
+        *mass = ColliderMassProperties::Mass(stats.mass());
+    }
+}
+
+// Karts touching shove each other apart, the lighter one taking more of it.
+// Star karts flatten whoever they hit, boosting karts ram without being pushed back.
+fn bump_karts(
+    mut kart_query: Query<(Entity, &Transform, &mut Velocity, &Kart, &KartStats), Without<Respawn>>,
+    mut hit_events: EventWriter<KartHit>,
+) {
+    let karts: Vec<(Entity, Vec3, Vec3, f32, bool, bool, bool)> = kart_query.iter()
+        .map(|(entity, transform, velocity, kart, stats)| (
+            entity,
+            transform.translation,
+            velocity.linvel,
+            stats.weight,
+            kart.star_timer > 0.0,
+            kart.is_boosting,
+            kart.spinout_timer > 0.0,
+        ))
+        .collect();
+
+    for (i, a) in karts.iter().enumerate() {
+        for b in &karts[i + 1..] {
+            let offset = b.1 - a.1;
+            let flat = Vec3::new(offset.x, 0.0, offset.z);
+            if flat.length() > BUMP_RADIUS * 2.0 || offset.y.abs() > 0.6 {
+                continue;
+            }
+            // From a to b
+            let normal = flat.normalize_or(Vec3::X);
+            let closing = (a.2 - b.2).dot(normal).max(0.0);
+            let shove = (closing + BUMP_MIN_SHOVE) * BUMP_STRENGTH;
+
+            // Share of the shove each kart takes, by the other's weight
+            let total = a.3 + b.3;
+            let (mut share_a, mut share_b) = (b.3 / total, a.3 / total);
+            let ram_a = (a.4 && !b.4) || (a.5 && !b.5 && !b.4);
+            let ram_b = (b.4 && !a.4) || (b.5 && !a.5 && !a.4);
+            if ram_a {
+                share_a = 0.0;
+                share_b *= RAM_MULTIPLIER;
+            } else if ram_b {
+                share_b = 0.0;
+                share_a *= RAM_MULTIPLIER;
+            }
+
+            // Stars spin out the kart they hit, once
+            if a.4 && !b.4 && !b.6 {
+                hit_events.send(KartHit { kart: b.0 });
+            } else if b.4 && !a.4 && !a.6 {
+                hit_events.send(KartHit { kart: a.0 });
+            }
+
+            if let Ok((_, _, mut velocity, _, _)) = kart_query.get_mut(a.0) {
+                velocity.linvel -= normal * shove * share_a;
+            }
+            if let Ok((_, _, mut velocity, _, _)) = kart_query.get_mut(b.0) {
+                velocity.linvel += normal * shove * share_b;
+            }
+        }
+    }
+}
+
+// Kill volumes, falling off the world or pressing reset call Lakitu
 fn player_reset(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut Transform, &mut Velocity, &mut Kart)>,
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    query: Query<(Entity, &Transform, &KartInput), (With<Kart>, Without<Respawn>)>,
+    kill_query: Query<(), With<KillVolume>>,
+    parent_query: Query<&Parent>,
+    track: Res<TrackDefinition>,
 ) {
-    for (mut transform, mut velocity, kart) in query.iter_mut() {
-        if transform.translation.y < -10.0 || keyboard.just_pressed(KeyCode::KeyR) {
-            transform.translation = kart.last_safe_pos + Vec3::Y * 2.0;
-            transform.rotation = kart.last_safe_rot;
+    for event in collision_events.read() {
+        if let CollisionEvent::Started(e1, e2, _) = event {
+            let kart = if is_kill_volume(*e1, &kill_query, &parent_query) { *e2 }
+                       else if is_kill_volume(*e2, &kill_query, &parent_query) { *e1 }
+                       else { continue; };
+            if query.contains(kart) {
+                commands.entity(kart).insert(Respawn::default());
+            }
+        }
+    }
+
+    for (entity, transform, input) in query.iter() {
+        if transform.translation.y < track.fall_height || input.reset {
+            commands.entity(entity).insert(Respawn::default());
+        }
+    }
+}
+
+fn respawn_karts(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Respawn, &mut Transform, &mut Velocity, &Kart, &mut crate::logic::PlayerStats)>,
+    spline: Res<TrackSpline>,
+    time: Res<Time>,
+) {
+    for (entity, mut respawn, mut transform, mut velocity, kart, mut stats) in query.iter_mut() {
+        respawn.elapsed += time.delta_secs();
+
+        if respawn.elapsed >= RESPAWN_FADE_OUT && !respawn.placed {
+            respawn.placed = true;
+            stats.penalty += RESPAWN_PENALTY;
+
+            // Behind the last spot the kart was on the track (never ahead, so resetting can't skip a corner)
+            let s = spline.project(kart.last_safe_pos) - RESPAWN_BACKTRACK.min(spline.length * 0.02);
+            let (point, tangent) = spline.sample(s);
+            let heading = Vec3::new(tangent.x, 0.0, tangent.z).normalize_or(Vec3::NEG_Z);
+            *transform = Transform::from_translation(point + Vec3::Y * RESPAWN_DROP_HEIGHT).looking_to(heading, Vec3::Y);
+            info!("Respawned {:?} on the track", entity);
+        }
+
+        // Held still above the track, then dropped
+        if respawn.placed && respawn.elapsed < RESPAWN_FADE_OUT + RESPAWN_HOLD {
             velocity.linvel = Vec3::ZERO;
             velocity.angvel = Vec3::ZERO;
         }
+
+        if respawn.elapsed >= RESPAWN_TIME {
+            commands.entity(entity).remove::<Respawn>();
+        }
     }
 }
 
 fn camera_follow(
     kart_query: Query<&Transform, (With<Kart>, Without<FollowCamera>)>,
-    mut cam_query: Query<&mut Transform, With<FollowCamera>>,
+    mut cam_query: Query<(&mut Transform, &mut FollowCamera)>,
+    mut wall_events: EventReader<WallHit>,
     time: Res<Time>,
 ) {
-    if let Ok(kart_transform) = kart_query.get_single() {
-        if let Ok(mut cam_transform) = cam_query.get_single_mut() {
+    for event in wall_events.read() {
+        for (_, mut follow) in cam_query.iter_mut() {
+            if follow.target == event.kart {
+                follow.shake = follow.shake.max(event.strength);
+            }
+        }
+    }
+
+    for (mut cam_transform, mut follow) in cam_query.iter_mut() {
+        if let Ok(kart_transform) = kart_query.get(follow.target) {
             let dt = time.delta_secs();
             let target_pos = kart_transform.translation + *kart_transform.back() * 3.0 + Vec3::Y * 1.5;
             cam_transform.translation = cam_transform.translation.lerp(target_pos, 4.0 * dt);
-            
+
             let look_at = kart_transform.translation + Vec3::Y * 0.8;
             cam_transform.look_at(look_at, Vec3::Y);
+
+            // Shake: a quick wobble on top of the look-at, fading out
+            if follow.shake > 0.0 {
+                let t = time.elapsed_secs();
+                let amount = follow.shake * follow.shake * 0.05;
+                cam_transform.rotate_local_x((t * 47.0).sin() * amount);
+                cam_transform.rotate_local_y((t * 61.0).sin() * amount);
+                follow.shake = (follow.shake - 2.5 * dt).max(0.0);
+            }
+        }
+    }
+}
+
+// Screen area of a player: full screen solo, stacked halves for two, quadrants for three or four
+pub fn split_screen_rect(player: usize, player_count: usize, window_size: UVec2) -> (UVec2, UVec2) {
+    match player_count {
+        0 | 1 => (UVec2::ZERO, window_size),
+        2 => {
+            let size = UVec2::new(window_size.x, window_size.y / 2);
+            (UVec2::new(0, size.y * player as u32), size)
+        }
+        _ => {
+            let size = window_size / 2;
+            (UVec2::new(size.x * (player as u32 % 2), size.y * (player as u32 / 2)), size)
+        }
+    }
+}
+
+fn update_viewports(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut cam_query: Query<(&mut Camera, &FollowCamera)>,
+    local_players: Res<LocalPlayers>,
+) {
+    let Ok(window) = window_query.get_single() else { return; };
+    if local_players.0 <= 1 {
+        return;
+    }
+
+    let window_size = window.physical_size();
+    for (mut camera, follow) in cam_query.iter_mut() {
+        let (physical_position, physical_size) = split_screen_rect(follow.player, local_players.0, window_size);
+        let unchanged = camera.viewport.as_ref()
+            .is_some_and(|v| v.physical_position == physical_position && v.physical_size == physical_size);
+        if !unchanged {
+            camera.viewport = Some(Viewport {
+                physical_position,
+                physical_size,
+                ..default()
+            });
         }
     }
 }
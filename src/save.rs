@@ -0,0 +1,63 @@
+// Small bits of player data kept between launches: one file per key on native, localStorage on the web
+
+use bevy::prelude::*;
+
+// Where saves go, the localStorage key prefix on the web. Headless apps use a fresh temporary one.
+#[derive(Resource, Clone)]
+pub struct SaveDir(pub std::path::PathBuf);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for SaveDir {
+    fn default() -> Self {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        match home {
+            Some(home) => Self(std::path::PathBuf::from(home).join(".mariok-bevy")),
+            None => Self(std::path::PathBuf::from("saves")),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Default for SaveDir {
+    fn default() -> Self {
+        Self(std::path::PathBuf::from("mariok-bevy"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_path(dir: &SaveDir, key: &str) -> std::path::PathBuf {
+    dir.0.join(format!("{}.json", key))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(dir: &SaveDir, key: &str) -> Option<String> {
+    std::fs::read_to_string(save_path(dir, key)).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(dir: &SaveDir, key: &str, contents: &str) {
+    let path = save_path(dir, key);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(err) = std::fs::write(&path, contents) {
+        warn!("Can't save {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(dir: &SaveDir, key: &str) -> Option<String> {
+    storage()?.get_item(&format!("{}/{}", dir.0.display(), key)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(dir: &SaveDir, key: &str, contents: &str) {
+    if let Some(storage) = storage() {
+        let _ = storage.set_item(&format!("{}/{}", dir.0.display(), key), contents);
+    }
+}
@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+use bevy::gltf::GltfAssetLabel;
+use crate::input::MenuInput;
+use crate::logic::RacePhase;
+use crate::menu::Screen;
+use crate::player::{KartBuild, KartCatalog, KartPart, KartSelections, KartStats, LocalPlayer, LocalPlayers, DEFAULT_KART_MODEL};
+
+// Before the countdown each local player in turn picks a character, kart body and wheels (added by MenuPlugin)
+pub struct SelectPlugin;
+
+impl Plugin for SelectPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_state(RacePhase::Lobby)
+           .add_systems(OnEnter(Screen::Select), setup_select_screen)
+           .add_systems(Update, (
+               select_input,
+               update_select_ui,
+               spin_turntable,
+           ).chain().run_if(resource_exists::<SelectScreen>));
+    }
+}
+
+// Far above the track and past the race cameras' far plane, so neither sees the other
+const PREVIEW_SPOT: Vec3 = Vec3::new(0.0, 5000.0, 0.0);
+const TURNTABLE_SPEED: f32 = 0.8;
+
+const ROW_LABELS: [&str; 3] = ["Character", "Kart", "Wheels"];
+
+const STAT_LABELS: [&str; 5] = ["Speed", "Acceleration", "Weight", "Handling", "Traction"];
+
+#[derive(Resource)]
+struct SelectScreen {
+    // Whose turn it is
+    player: usize,
+    row: usize,
+    // Index into the catalog's characters, karts and wheels
+    choice: [usize; 3],
+    builds: Vec<KartBuild>,
+}
+
+impl SelectScreen {
+    fn current_build(&self, catalog: &KartCatalog) -> KartBuild {
+        let name = |parts: &[KartPart], index: usize| {
+            parts.get(index).map(|part| part.name.clone()).unwrap_or_default()
+        };
+        KartBuild {
+            character: name(&catalog.characters, self.choice[0]),
+            kart: name(&catalog.karts, self.choice[1]),
+            wheels: name(&catalog.wheels, self.choice[2]),
+        }
+    }
+}
+
+#[derive(Component)]
+struct SelectTitle;
+
+#[derive(Component)]
+struct SelectRow(usize);
+
+#[derive(Component)]
+struct StatBar(usize);
+
+#[derive(Component)]
+struct Turntable;
+
+fn setup_select_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SelectScreen { player: 0, row: 0, choice: [0; 3], builds: Vec::new() });
+
+    // Turntable with its own camera, drawn over the race cameras
+    let camera = commands.spawn((
+        Camera3d::default(),
+        Camera { order: 100, ..default() },
+        Transform::from_translation(PREVIEW_SPOT + Vec3::new(-1.2, 1.2, 3.5))
+            .looking_at(PREVIEW_SPOT + Vec3::new(-1.2, 0.3, 0.0), Vec3::Y),
+        StateScoped(Screen::Select),
+    )).id();
+    commands.spawn((
+        Transform::from_translation(PREVIEW_SPOT),
+        Visibility::default(),
+        Turntable,
+        StateScoped(Screen::Select),
+    )).with_children(|parent| {
+        parent.spawn((
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(DEFAULT_KART_MODEL))),
+            Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::PI))
+                .with_scale(Vec3::splat(0.01)),
+        ));
+    });
+
+    let font = asset_server.load("fonts/HK.ttf");
+    let text = |size: f32| TextFont { font: font.clone(), font_size: size, ..default() };
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            TargetCamera(camera),
+            StateScoped(Screen::Select),
+        ))
+        .with_children(|parent| {
+            // Left panel, the turntable shows on the right
+            parent.spawn((
+                Node {
+                    width: Val::Percent(40.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(40.0)),
+                    row_gap: Val::Px(16.0),
+                    ..default()
+                },
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            )).with_children(|panel| {
+                panel.spawn((Text::new(""), text(56.0), TextColor(Color::srgb(1.0, 0.85, 0.0)), SelectTitle));
+
+                for row in 0..ROW_LABELS.len() {
+                    panel.spawn((Text::new(""), text(36.0), TextColor(Color::WHITE), SelectRow(row)));
+                }
+
+                for (i, label) in STAT_LABELS.iter().enumerate() {
+                    panel.spawn(Node {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(12.0),
+                        ..default()
+                    }).with_children(|stat| {
+                        stat.spawn((
+                            Text::new(*label),
+                            text(24.0),
+                            TextColor(Color::WHITE),
+                            Node { width: Val::Px(170.0), ..default() },
+                        ));
+                        stat.spawn((
+                            Node { width: Val::Px(220.0), height: Val::Px(14.0), ..default() },
+                            BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.2)),
+                        )).with_children(|bar| {
+                            bar.spawn((
+                                Node { width: Val::Percent(50.0), height: Val::Percent(100.0), ..default() },
+                                BackgroundColor(Color::srgb(0.2, 0.8, 1.0)),
+                                StatBar(i),
+                            ));
+                        });
+                    });
+                }
+
+                panel.spawn((
+                    Text::new("Up/Down: choose part   Left/Right: change   Enter: confirm   Esc: back"),
+                    text(18.0),
+                    TextColor(Color::srgb(0.8, 0.8, 0.8)),
+                ));
+            });
+        });
+}
+
+fn select_input(
+    mut commands: Commands,
+    menu: Res<MenuInput>,
+    mut screen: ResMut<SelectScreen>,
+    catalog: Res<KartCatalog>,
+    local_players: Res<LocalPlayers>,
+    mut kart_query: Query<(&LocalPlayer, &mut KartBuild)>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+) {
+    let row_len = [catalog.characters.len(), catalog.karts.len(), catalog.wheels.len()];
+
+    if menu.up {
+        screen.row = (screen.row + ROW_LABELS.len() - 1) % ROW_LABELS.len();
+    }
+    if menu.down {
+        screen.row = (screen.row + 1) % ROW_LABELS.len();
+    }
+    let row = screen.row;
+    if row_len[row] > 0 {
+        if menu.left {
+            screen.choice[row] = (screen.choice[row] + row_len[row] - 1) % row_len[row];
+        }
+        if menu.right {
+            screen.choice[row] = (screen.choice[row] + 1) % row_len[row];
+        }
+    }
+
+    if menu.back {
+        if screen.player == 0 {
+            commands.remove_resource::<SelectScreen>();
+            next_screen.set(Screen::MainMenu);
+            return;
+        }
+        screen.player -= 1;
+        screen.builds.pop();
+        screen.row = 0;
+    }
+
+    if menu.confirm {
+        let build = screen.current_build(&catalog);
+        screen.builds.push(build);
+        screen.player += 1;
+        screen.row = 0;
+
+        if screen.player >= local_players.0 {
+            // Everyone picked: dress the karts on the grid and start the countdown
+            for (local, mut build) in kart_query.iter_mut() {
+                if let Some(picked) = screen.builds.get(local.index) {
+                    *build = picked.clone();
+                }
+            }
+            info!("Selected builds: {:?}", screen.builds);
+            commands.insert_resource(KartSelections(screen.builds.clone()));
+            commands.remove_resource::<SelectScreen>();
+            next_screen.set(Screen::Race);
+            next_phase.set(RacePhase::Countdown);
+        }
+    }
+}
+
+// Bar position of a stat against the base build: half full for a normal kart
+fn stat_fill(stats: &KartStats, base: &KartStats, stat: usize) -> f32 {
+    let (value, normal, spread) = match stat {
+        0 => (stats.speed, base.speed, 10.0),
+        1 => (stats.acceleration, base.acceleration, 4.0),
+        2 => (stats.weight, base.weight, 1.2),
+        3 => (stats.handling, base.handling, 1.2),
+        _ => (stats.traction, base.traction, 0.6),
+    };
+    (0.5 + (value - normal) / spread).clamp(0.05, 1.0)
+}
+
+fn update_select_ui(
+    screen: Res<SelectScreen>,
+    catalog: Res<KartCatalog>,
+    asset_server: Res<AssetServer>,
+    mut title_query: Query<&mut Text, (With<SelectTitle>, Without<SelectRow>)>,
+    mut row_query: Query<(&mut Text, &mut TextColor, &SelectRow)>,
+    mut bar_query: Query<(&mut Node, &StatBar)>,
+    turntable_query: Query<&Children, With<Turntable>>,
+    mut scene_query: Query<&mut SceneRoot>,
+) {
+    if !screen.is_changed() {
+        return;
+    }
+    let build = screen.current_build(&catalog);
+
+    for mut text in title_query.iter_mut() {
+        text.0 = format!("Player {}", screen.player + 1);
+    }
+
+    let names = [&build.character, &build.kart, &build.wheels];
+    for (mut text, mut color, row) in row_query.iter_mut() {
+        text.0 = format!("{}:  < {} >", ROW_LABELS[row.0], names[row.0]);
+        color.0 = if row.0 == screen.row { Color::srgb(1.0, 0.85, 0.0) } else { Color::WHITE };
+    }
+
+    let stats = catalog.stats(&build);
+    for (mut node, bar) in bar_query.iter_mut() {
+        node.width = Val::Percent(stat_fill(&stats, &catalog.base, bar.0) * 100.0);
+    }
+
+    let model = asset_server.load(GltfAssetLabel::Scene(0).from_asset(catalog.model(&build).to_string()));
+    for children in turntable_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut scene) = scene_query.get_mut(*child) {
+                if scene.0 != model {
+                    scene.0 = model.clone();
+                }
+            }
+        }
+    }
+}
+
+fn spin_turntable(mut query: Query<&mut Transform, With<Turntable>>, time: Res<Time>) {
+    for mut transform in query.iter_mut() {
+        transform.rotate_y(TURNTABLE_SPEED * time.delta_secs());
+    }
+}
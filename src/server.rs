@@ -0,0 +1,396 @@
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::render::settings::{RenderCreation, WgpuSettings};
+use bevy::render::RenderPlugin;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+use bevy_rapier3d::prelude::*;
+use tungstenite::{Message, WebSocket};
+use crate::input::KartInput;
+use crate::items::{Banana, BrokenItemBox, Coin, HeldItem, Item, ItemBox, ItemsPlugin, LayoutIndex, Shell};
+use crate::logic::{Countdown, LogicPlugin, PlayerStats, RaceConfig, RacePhase};
+use crate::net::{accept_websocket, read_websocket, ClientMessage, ItemState, KartState, ServerMessage};
+use crate::player::{grid_transform, spawn_kart, Kart, LocalPlayers, PlayerPlugin};
+use crate::track::{LoadTrack, TrackDefinition, TrackPlugin};
+
+const TICK_RATE: f64 = 60.0;
+// Clients silent for this long are dropped
+const CLIENT_TIMEOUT: f32 = 5.0;
+
+// Stop waiting for missing players after this long and start anyway
+const LOBBY_TIMEOUT: f32 = 20.0;
+
+// Results stay up this long once everyone finished, then the server exits
+const RESULTS_TIME: f32 = 5.0;
+
+pub struct ServerConfig {
+    pub udp_addr: String,
+    pub ws_addr: String,
+    // The countdown starts once this many players joined
+    pub expected_players: usize,
+    // Track of the room, from `--track`
+    pub track: String,
+    pub race: RaceConfig,
+}
+
+// Authoritative server: the real game simulation with no window, renderer or audio.
+// The renderer plugin stays (with no GPU backend) so the track GLB still loads for its colliders.
+pub fn run(config: ServerConfig) {
+    let mut state = ServerState::bind(&config.udp_addr, &config.ws_addr)
+        .unwrap_or_else(|err| panic!("Can't bind server sockets: {}", err));
+    state.expected_players = config.expected_players.max(1);
+    info!("Server listening on udp://{} and ws://{}", config.udp_addr, config.ws_addr);
+
+    let mut app = App::new();
+    app
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: None,
+            exit_condition: ExitCondition::DontExit,
+            close_when_requested: false,
+        }).set(RenderPlugin {
+            render_creation: RenderCreation::Automatic(WgpuSettings {
+                backends: None,
+                ..default()
+            }),
+            ..default()
+        }).disable::<WinitPlugin>().disable::<bevy::audio::AudioPlugin>())
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / TICK_RATE)))
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+        // Every kart on the server belongs to a remote client
+        .insert_resource(LocalPlayers(0))
+        .insert_resource(config.race)
+        .add_plugins((TrackPlugin, PlayerPlugin, crate::input::InputPlugin, LogicPlugin, ItemsPlugin))
+        .insert_state(RacePhase::Lobby)
+        .insert_resource(state)
+        .add_systems(PreUpdate, server_receive.after(crate::input::gather_input))
+        .add_systems(Update, (
+            start_when_ready.run_if(in_state(RacePhase::Lobby)),
+            stop_when_empty.run_if(not(in_state(RacePhase::Lobby))),
+            stop_when_finished.run_if(in_state(RacePhase::Racing)),
+        ))
+        .add_systems(PostUpdate, server_broadcast.after(PhysicsSet::Writeback))
+        .add_systems(Last, clear_one_shot_inputs);
+
+    info!("Racing on {}", config.track);
+    app.world_mut().send_event(LoadTrack(config.track));
+    app.run();
+}
+
+enum Connection {
+    Udp(SocketAddr),
+    WebSocket(Box<WebSocket<TcpStream>>),
+}
+
+struct RemoteClient {
+    player_id: u32,
+    connection: Connection,
+    kart: Entity,
+    last_seq: u32,
+    last_heard: f32,
+}
+
+#[derive(Resource)]
+struct ServerState {
+    udp: UdpSocket,
+    ws_listener: TcpListener,
+    // WebSocket connections that haven't sent Join yet
+    pending: Vec<WebSocket<TcpStream>>,
+    clients: Vec<RemoteClient>,
+    next_player_id: u32,
+    tick: u32,
+    expected_players: usize,
+    // When the first player joined
+    first_join: Option<f32>,
+}
+
+impl ServerState {
+    fn bind(udp_addr: &str, ws_addr: &str) -> std::io::Result<Self> {
+        let udp = UdpSocket::bind(udp_addr)?;
+        udp.set_nonblocking(true)?;
+        let ws_listener = TcpListener::bind(ws_addr)?;
+        ws_listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            udp,
+            ws_listener,
+            pending: Vec::new(),
+            clients: Vec::new(),
+            next_player_id: 1,
+            tick: 0,
+            expected_players: 1,
+            first_join: None,
+        })
+    }
+
+    fn send(udp: &UdpSocket, connection: &mut Connection, message: &ServerMessage) {
+        let Ok(json) = serde_json::to_string(message) else { return; };
+        match connection {
+            Connection::Udp(addr) => {
+                let _ = udp.send_to(json.as_bytes(), *addr);
+            }
+            Connection::WebSocket(socket) => {
+                let _ = socket.send(Message::text(json));
+            }
+        }
+    }
+}
+
+fn server_receive(
+    mut commands: Commands,
+    mut state: ResMut<ServerState>,
+    mut kart_query: Query<&mut KartInput>,
+    asset_server: Res<AssetServer>,
+    phase: Res<State<RacePhase>>,
+    track: Res<TrackDefinition>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let state = &mut *state;
+
+    // New WebSocket connections
+    while let Ok((stream, addr)) = state.ws_listener.accept() {
+        match accept_websocket(stream) {
+            Some(socket) => state.pending.push(socket),
+            None => warn!("WebSocket handshake with {} failed", addr),
+        }
+    }
+
+    // Everything received this tick, tagged with who sent it
+    let mut inbox: Vec<(Connection, ClientMessage)> = Vec::new();
+
+    let mut buf = [0u8; 65536];
+    while let Ok((len, addr)) = state.udp.recv_from(&mut buf) {
+        if let Ok(message) = serde_json::from_slice(&buf[..len]) {
+            inbox.push((Connection::Udp(addr), message));
+        }
+    }
+
+    let mut still_pending = Vec::new();
+    for mut socket in state.pending.drain(..) {
+        match read_websocket::<ClientMessage, _>(&mut socket) {
+            Some(messages) if messages.iter().any(|m| matches!(m, ClientMessage::Join)) => {
+                inbox.push((Connection::WebSocket(Box::new(socket)), ClientMessage::Join));
+            }
+            Some(_) => still_pending.push(socket),
+            None => {}
+        }
+    }
+    state.pending = still_pending;
+
+    // Joined WebSocket clients are read in place, a closed socket counts as leaving
+    let mut closed = Vec::new();
+    for client in state.clients.iter_mut() {
+        let received = match &mut client.connection {
+            Connection::WebSocket(socket) => read_websocket(socket.as_mut()),
+            Connection::Udp(_) => continue,
+        };
+        match received {
+            Some(messages) => {
+                for message in messages {
+                    if matches!(message, ClientMessage::Leave) {
+                        closed.push(client.player_id);
+                    } else {
+                        apply_message(client, message, &mut kart_query, now);
+                    }
+                }
+            }
+            None => closed.push(client.player_id),
+        }
+    }
+
+    for (connection, message) in inbox {
+        let existing = match &connection {
+            Connection::Udp(addr) => state.clients.iter_mut().find(|c| matches!(c.connection, Connection::Udp(a) if a == *addr)),
+            Connection::WebSocket(_) => None,
+        };
+
+        if let Some(client) = existing {
+            if matches!(message, ClientMessage::Leave) {
+                closed.push(client.player_id);
+            } else if matches!(message, ClientMessage::Join) {
+                // Our Welcome got lost, say it again
+                let player_id = client.player_id;
+                ServerState::send(&state.udp, &mut client.connection, &ServerMessage::Welcome { player_id });
+            } else {
+                apply_message(client, message, &mut kart_query, now);
+            }
+        } else if matches!(message, ClientMessage::Join) {
+            // One kart per expected player, and nobody joins a race in progress
+            let refusal = if *phase.get() != RacePhase::Lobby {
+                Some("The race already started")
+            } else if state.clients.len() >= state.expected_players {
+                Some("The race is full")
+            } else {
+                None
+            };
+            if let Some(reason) = refusal {
+                let mut connection = connection;
+                ServerState::send(&state.udp, &mut connection, &ServerMessage::Rejected { reason: reason.to_string() });
+                info!("Turned a player away: {}", reason);
+                continue;
+            }
+
+            let player_id = state.next_player_id;
+            state.next_player_id += 1;
+
+            // Start grid, one slot per player
+            let (start_pos, start_rot) = grid_transform(player_id as usize - 1, &track);
+            let kart = spawn_kart(&mut commands, &asset_server, start_pos, start_rot);
+
+            let mut client = RemoteClient { player_id, connection, kart, last_seq: 0, last_heard: now };
+            ServerState::send(&state.udp, &mut client.connection, &ServerMessage::Welcome { player_id });
+            state.clients.push(client);
+            state.first_join.get_or_insert(now);
+            info!("Player {} joined", player_id);
+        }
+    }
+
+    // Drop whoever left or went quiet
+    state.clients.retain(|client| {
+        let gone = closed.contains(&client.player_id) || now - client.last_heard > CLIENT_TIMEOUT;
+        if gone {
+            info!("Player {} left", client.player_id);
+            commands.entity(client.kart).despawn_recursive();
+        }
+        !gone
+    });
+}
+
+fn start_when_ready(
+    state: Res<ServerState>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+    time: Res<Time>,
+) {
+    let Some(first_join) = state.first_join else { return; };
+    let everyone_here = state.clients.len() >= state.expected_players;
+    if everyone_here || time.elapsed_secs() - first_join > LOBBY_TIMEOUT {
+        info!("Starting the race with {} players", state.clients.len());
+        next_phase.set(RacePhase::Countdown);
+    }
+}
+
+// Everybody left after the start, nothing left to simulate (the lobby reopens the room)
+fn stop_when_empty(state: Res<ServerState>, mut exit: EventWriter<AppExit>) {
+    if state.clients.is_empty() {
+        info!("Everybody left, shutting down");
+        exit.send(AppExit::Success);
+    }
+}
+
+// Everyone crossed the line: the lobby reopens the room once this process is gone
+fn stop_when_finished(
+    state: Res<ServerState>,
+    stats_query: Query<&PlayerStats>,
+    time: Res<Time>,
+    mut finished_at: Local<Option<f32>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let everyone_finished = !state.clients.is_empty()
+        && state.clients.iter().all(|client| stats_query.get(client.kart).is_ok_and(|stats| stats.finished));
+    if !everyone_finished {
+        return;
+    }
+    let now = time.elapsed_secs();
+    if now - *finished_at.get_or_insert(now) > RESULTS_TIME {
+        info!("Race over, shutting down");
+        exit.send(AppExit::Success);
+    }
+}
+
+fn apply_message(client: &mut RemoteClient, message: ClientMessage, kart_query: &mut Query<&mut KartInput>, now: f32) {
+    client.last_heard = now;
+    if let ClientMessage::Input { seq, input } = message {
+        // UDP can reorder, never go back to an older input
+        if seq <= client.last_seq {
+            return;
+        }
+        client.last_seq = seq;
+        if let Ok(mut kart_input) = kart_query.get_mut(client.kart) {
+            // Button presses are one-shot, keep them until the simulation has seen them
+            let use_item = kart_input.use_item || input.use_item;
+            let reset = kart_input.reset || input.reset;
+            *kart_input = input;
+            kart_input.use_item = use_item;
+            kart_input.reset = reset;
+        }
+    }
+}
+
+fn server_broadcast(
+    mut state: ResMut<ServerState>,
+    kart_query: Query<(&Transform, &Velocity, &Kart, &PlayerStats, &HeldItem)>,
+    item_query: Query<(Entity, &Transform, Option<&Shell>), Or<(With<Banana>, With<Shell>)>>,
+    box_query: Query<&LayoutIndex, (With<ItemBox>, Without<BrokenItemBox>)>,
+    coin_query: Query<&LayoutIndex, With<Coin>>,
+    track: Res<TrackDefinition>,
+    phase: Res<State<RacePhase>>,
+    countdown: Res<Countdown>,
+) {
+    let state = &mut *state;
+    state.tick += 1;
+
+    let karts: Vec<KartState> = state.clients.iter()
+        .filter_map(|client| {
+            let (transform, velocity, kart, stats, held) = kart_query.get(client.kart).ok()?;
+            Some(KartState {
+                player_id: client.player_id,
+                position: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+                linvel: velocity.linvel.to_array(),
+                last_input_seq: client.last_seq,
+                lap: stats.current_lap,
+                last_checkpoint: stats.last_checkpoint,
+                coins: stats.coin_count,
+                item: held.item,
+                boosting: kart.is_boosting,
+            })
+        })
+        .collect();
+
+    let items = item_query.iter()
+        .map(|(entity, transform, shell)| ItemState {
+            id: entity.to_bits(),
+            item: match shell {
+                Some(shell) if shell.target.is_some() => Item::RedShell,
+                Some(_) => Item::GreenShell,
+                None => Item::Banana,
+            },
+            position: transform.translation.to_array(),
+        })
+        .collect();
+
+    let snapshot = ServerMessage::Snapshot {
+        tick: state.tick,
+        phase: *phase.get(),
+        countdown: countdown.remaining,
+        karts,
+        items,
+        boxes: layout_flags(track.item_boxes.len(), box_query.iter()),
+        coins: layout_flags(track.coins.len(), coin_query.iter()),
+    };
+    for client in state.clients.iter_mut() {
+        ServerState::send(&state.udp, &mut client.connection, &snapshot);
+    }
+}
+
+// Which entries of a track layout list are still on the track
+fn layout_flags<'a>(len: usize, present: impl Iterator<Item = &'a LayoutIndex>) -> Vec<bool> {
+    let mut flags = vec![false; len];
+    for index in present {
+        if let Some(flag) = flags.get_mut(index.0) {
+            *flag = true;
+        }
+    }
+    flags
+}
+
+fn clear_one_shot_inputs(state: Res<ServerState>, mut kart_query: Query<&mut KartInput>) {
+    for client in &state.clients {
+        if let Ok(mut input) = kart_query.get_mut(client.kart) {
+            input.use_item = false;
+            input.reset = false;
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use crate::player::Kart;
+use crate::player::{Kart, KartLanded, LocalPlayer, WallHit};
 
 pub struct SoundsPlugin;
 
@@ -10,6 +10,15 @@ impl Plugin for SoundsPlugin {
     }
 }
 
+// One-shot sounds for what happens to the local karts, independent of the engine loop above
+pub struct SoundEffectsPlugin;
+
+impl Plugin for SoundEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (play_landing_sounds, play_bump_sounds));
+    }
+}
+
 #[derive(Component)]
 struct EngineSound;
 
@@ -44,11 +53,12 @@ fn setup_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
 }
 
 fn update_sounds(
-    kart_query: Query<&Kart>,
+    kart_query: Query<(&Kart, &LocalPlayer)>,
     mut engine_query: Query<(&mut PlaybackSettings, &AudioSink), (With<EngineSound>, Without<DriftSound>)>,
     mut drift_query: Query<(&mut PlaybackSettings, &AudioSink), (With<DriftSound>, Without<EngineSound>)>,
 ) {
-    if let Ok(kart) = kart_query.get_single() {
+    // A single set of speakers: the engine follows player one
+    if let Some((kart, _)) = kart_query.iter().find(|(_, local)| local.index == 0) {
         // Engine
         if let Ok((mut settings, sink)) = engine_query.get_single_mut() {
             if kart.speed.abs() > 0.1 {
@@ -73,3 +83,47 @@ fn update_sounds(
         }
     }
 }
+
+fn play_landing_sounds(
+    mut commands: Commands,
+    mut landed_events: EventReader<KartLanded>,
+    local_query: Query<(), With<LocalPlayer>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in landed_events.read() {
+        if !local_query.contains(event.kart) {
+            continue;
+        }
+        commands.spawn((
+            AudioPlayer::new(asset_server.load("sounds/landing.wav")),
+            PlaybackSettings::DESPAWN,
+        ));
+        // A trick landing gets the boost sound on top
+        if event.trick {
+            commands.spawn((
+                AudioPlayer::new(asset_server.load("sounds/turbo.wav")),
+                PlaybackSettings::DESPAWN,
+            ));
+        }
+    }
+}
+
+// No dedicated asset: the landing thud, pitched up and as loud as the hit
+fn play_bump_sounds(
+    mut commands: Commands,
+    mut wall_events: EventReader<WallHit>,
+    local_query: Query<(), With<LocalPlayer>>,
+    asset_server: Res<AssetServer>,
+) {
+    for event in wall_events.read() {
+        if !local_query.contains(event.kart) {
+            continue;
+        }
+        commands.spawn((
+            AudioPlayer::new(asset_server.load("sounds/landing.wav")),
+            PlaybackSettings::DESPAWN
+                .with_speed(1.5)
+                .with_volume(bevy::audio::Volume::new(0.3 + 0.7 * event.strength)),
+        ));
+    }
+}
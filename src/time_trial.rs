@@ -0,0 +1,512 @@
+use bevy::prelude::*;
+use bevy::gltf::GltfAssetLabel;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::input::MenuInput;
+use crate::items::{HeldItem, Item, ItemBox};
+use crate::logic::{CheckpointPassed, GameMode, PlayerStats, RaceClock, RaceConfig, RaceFinished, RacePhase};
+use crate::menu::Screen;
+use crate::player::{FollowCamera, KartCatalog, KartSelections, LocalPlayer, LocalPlayers};
+use crate::save::SaveDir;
+use crate::track::TrackDefinition;
+
+// Alone on the track against the clock: three mushrooms, no item boxes, splits against the best run
+// and its ghost. Other split-screen players sit out until the main menu.
+pub struct TimeTrialPlugin;
+
+impl Plugin for TimeTrialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeTrial>()
+           .init_resource::<SaveDir>()
+           .add_systems(OnEnter(Screen::Select), bench_other_players.run_if(is_time_trial))
+           .add_systems(OnEnter(Screen::MainMenu), unbench_players)
+           .add_systems(OnEnter(Screen::Race), (start_run, give_mushrooms, spawn_time_trial_hud).run_if(is_time_trial))
+           .add_systems(OnExit(Screen::Race), enable_item_boxes)
+           .add_systems(OnEnter(Screen::Results), spawn_results)
+           .add_systems(Update, (
+               (
+                   load_best.run_if(resource_changed::<TrackDefinition>),
+                   disable_item_boxes,
+                   record_run,
+                   update_ghost,
+                   update_time_trial_hud,
+                   open_results,
+               ).chain().run_if(in_state(Screen::Race).and(is_time_trial)),
+               results_input.run_if(in_state(Screen::Results)),
+           ));
+    }
+}
+
+// Where the ghost was at a point of the race
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct GhostFrame {
+    pub time: f32,
+    pub pos: [f32; 3],
+    pub rot: [f32; 4],
+}
+
+// A run: race time at every checkpoint passed, the total, and the path to replay as a ghost
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TimeTrialRecord {
+    pub total: f32,
+    pub splits: Vec<f32>,
+    pub ghost: Vec<GhostFrame>,
+}
+
+impl TimeTrialRecord {
+    // Where the ghost is at `time`, between the two closest samples
+    pub fn ghost_at(&self, time: f32) -> Option<Transform> {
+        let next = self.ghost.iter().position(|frame| frame.time > time);
+        let (a, b) = match next {
+            Some(0) => (self.ghost[0], self.ghost[0]),
+            Some(i) => (self.ghost[i - 1], self.ghost[i]),
+            None => {
+                let last = *self.ghost.last()?;
+                (last, last)
+            }
+        };
+        let t = if b.time > a.time { ((time - a.time) / (b.time - a.time)).clamp(0.0, 1.0) } else { 0.0 };
+        Some(Transform::from_translation(Vec3::from(a.pos).lerp(Vec3::from(b.pos), t))
+            .with_rotation(Quat::from_array(a.rot).slerp(Quat::from_array(b.rot), t)))
+    }
+}
+
+#[derive(Resource)]
+pub struct TimeTrial {
+    // Show the best run's ghost, toggled on the result screen
+    pub ghost: bool,
+    pub best: Option<TimeTrialRecord>,
+    pub run: TimeTrialRecord,
+    // Save key of the track being raced
+    key: String,
+    // Last split against the best: difference, and how long it has been on screen
+    split: Option<(f32, f32)>,
+    new_record: bool,
+    finished_for: f32,
+    // Split-screen player count before the others sat out
+    benched: Option<usize>,
+}
+
+impl Default for TimeTrial {
+    fn default() -> Self {
+        Self {
+            ghost: true,
+            best: None,
+            run: TimeTrialRecord::default(),
+            key: String::new(),
+            split: None,
+            new_record: false,
+            finished_for: 0.0,
+            benched: None,
+        }
+    }
+}
+
+const START_ITEM: Item = Item::TripleMushroom;
+// Seconds between ghost samples
+const GHOST_INTERVAL: f32 = 0.1;
+const SPLIT_SHOWN_FOR: f32 = 3.0;
+const RESULTS_DELAY: f32 = 2.0;
+
+#[derive(Component)]
+struct Ghost;
+
+#[derive(Component)]
+struct TimeTrialClock;
+
+#[derive(Component)]
+struct SplitText;
+
+#[derive(Component)]
+struct ResultsGhostLabel;
+
+fn is_time_trial(mode: Res<GameMode>) -> bool {
+    *mode == GameMode::TimeTrial
+}
+
+pub fn format_time(seconds: f32) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u32;
+    format!("{}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+fn format_split(diff: f32) -> String {
+    let sign = if diff < 0.0 { "-" } else { "+" };
+    format!("{}{:.3}", sign, diff.abs())
+}
+
+// Best runs are kept per track (and mirror/reverse variant), lap count and engine class
+fn record_key(track: &TrackDefinition, config: &RaceConfig) -> String {
+    format!("time_trial_{}{}_{}laps_{}", track.name, track.variant.suffix(), config.laps, config.cc.label())
+}
+
+// Only player 1 races, the other karts and their screens are put away
+fn bench_other_players(
+    mut commands: Commands,
+    mut time_trial: ResMut<TimeTrial>,
+    mut local_players: ResMut<LocalPlayers>,
+    kart_query: Query<(Entity, &LocalPlayer)>,
+    mut camera_query: Query<(&mut Camera, &FollowCamera)>,
+) {
+    if local_players.0 <= 1 || time_trial.benched.is_some() {
+        return;
+    }
+    time_trial.benched = Some(local_players.0);
+    local_players.0 = 1;
+
+    for (entity, local) in kart_query.iter() {
+        if local.index > 0 {
+            commands.entity(entity).insert((RigidBodyDisabled, Visibility::Hidden));
+        }
+    }
+    for (mut camera, follow) in camera_query.iter_mut() {
+        if follow.player > 0 {
+            camera.is_active = false;
+        } else {
+            camera.viewport = None;
+        }
+    }
+}
+
+fn unbench_players(
+    mut commands: Commands,
+    mut time_trial: ResMut<TimeTrial>,
+    mut local_players: ResMut<LocalPlayers>,
+    kart_query: Query<(Entity, &LocalPlayer)>,
+    mut camera_query: Query<&mut Camera, With<FollowCamera>>,
+) {
+    let Some(players) = time_trial.benched.take() else { return; };
+    local_players.0 = players;
+
+    for (entity, local) in kart_query.iter() {
+        if local.index > 0 {
+            commands.entity(entity).remove::<RigidBodyDisabled>().insert(Visibility::Inherited);
+        }
+    }
+    // update_viewports splits the screen again
+    for mut camera in camera_query.iter_mut() {
+        camera.is_active = true;
+    }
+}
+
+fn start_run(
+    mut time_trial: ResMut<TimeTrial>,
+    track: Res<TrackDefinition>,
+    config: Res<RaceConfig>,
+    save_dir: Res<SaveDir>,
+) {
+    time_trial.run = TimeTrialRecord::default();
+    time_trial.split = None;
+    time_trial.new_record = false;
+    time_trial.finished_for = 0.0;
+    read_best(&mut time_trial, &track, &config, &save_dir);
+}
+
+// Also when the track is swapped in after entering the race
+fn load_best(
+    mut time_trial: ResMut<TimeTrial>,
+    track: Res<TrackDefinition>,
+    config: Res<RaceConfig>,
+    save_dir: Res<SaveDir>,
+) {
+    read_best(&mut time_trial, &track, &config, &save_dir);
+}
+
+fn read_best(time_trial: &mut TimeTrial, track: &TrackDefinition, config: &RaceConfig, save_dir: &SaveDir) {
+    time_trial.key = record_key(track, config);
+    time_trial.best = crate::save::read(save_dir, &time_trial.key)
+        .and_then(|json| serde_json::from_str(&json).ok());
+}
+
+fn give_mushrooms(mut kart_query: Query<(&LocalPlayer, &mut HeldItem)>) {
+    for (local, mut held) in kart_query.iter_mut() {
+        if local.index == 0 {
+            held.item = Some(START_ITEM);
+            held.uses = START_ITEM.uses();
+        }
+    }
+}
+
+// Boxes come back with every track load, so they're switched off as they appear
+fn disable_item_boxes(
+    mut commands: Commands,
+    box_query: Query<Entity, (With<ItemBox>, Without<ColliderDisabled>)>,
+) {
+    for entity in box_query.iter() {
+        commands.entity(entity).insert((ColliderDisabled, Visibility::Hidden));
+    }
+}
+
+fn enable_item_boxes(mut commands: Commands, box_query: Query<Entity, (With<ItemBox>, With<ColliderDisabled>)>) {
+    for entity in box_query.iter() {
+        commands.entity(entity).remove::<ColliderDisabled>().insert(Visibility::Inherited);
+    }
+}
+
+fn record_run(
+    mut time_trial: ResMut<TimeTrial>,
+    mut checkpoint_events: EventReader<CheckpointPassed>,
+    mut finish_events: EventReader<RaceFinished>,
+    kart_query: Query<(Entity, &LocalPlayer, &Transform, &PlayerStats)>,
+    clock: Res<RaceClock>,
+    phase: Res<State<RacePhase>>,
+    save_dir: Res<SaveDir>,
+    time: Res<Time>,
+) {
+    let Some((kart, _, transform, stats)) = kart_query.iter().find(|(_, local, _, _)| local.index == 0) else { return; };
+    let race_time = clock.elapsed + stats.penalty;
+
+    if let Some((_, shown_for)) = time_trial.split.as_mut() {
+        *shown_for += time.delta_secs();
+    }
+
+    for event in checkpoint_events.read() {
+        // Crossing the start line on the green light isn't a split
+        if event.kart != kart || clock.elapsed <= 0.0 {
+            continue;
+        }
+        let split = time_trial.run.splits.len();
+        time_trial.run.splits.push(race_time);
+        let best_split = time_trial.best.as_ref().and_then(|best| best.splits.get(split).copied());
+        if let Some(best_split) = best_split {
+            time_trial.split = Some((race_time - best_split, 0.0));
+        }
+    }
+
+    if *phase.get() == RacePhase::Racing && !stats.finished {
+        let due = time_trial.run.ghost.last().is_none_or(|frame| clock.elapsed - frame.time >= GHOST_INTERVAL);
+        if due {
+            time_trial.run.ghost.push(GhostFrame {
+                time: clock.elapsed,
+                pos: transform.translation.into(),
+                rot: transform.rotation.to_array(),
+            });
+        }
+    }
+
+    for event in finish_events.read() {
+        if event.kart != kart || !stats.finished || time_trial.run.total > 0.0 {
+            continue;
+        }
+        time_trial.run.total = race_time;
+        let best_total = time_trial.best.as_ref().map(|best| best.total);
+        info!("Time trial: {} (best {:?})", format_time(race_time), best_total.map(format_time));
+        if best_total.is_none_or(|best| race_time < best) {
+            time_trial.new_record = true;
+            match serde_json::to_string(&time_trial.run) {
+                Ok(json) => crate::save::write(&save_dir, &time_trial.key, &json),
+                Err(err) => warn!("Can't save the time trial record: {}", err),
+            }
+        }
+    }
+}
+
+// The best run's kart, replayed on the race clock. No collider, it can't be hit.
+fn update_ghost(
+    mut commands: Commands,
+    time_trial: Res<TimeTrial>,
+    mut ghost_query: Query<(Entity, &mut Transform), With<Ghost>>,
+    clock: Res<RaceClock>,
+    asset_server: Res<AssetServer>,
+    catalog: Res<KartCatalog>,
+    selections: Res<KartSelections>,
+) {
+    let path = time_trial.best.as_ref()
+        .filter(|_| time_trial.ghost)
+        .and_then(|best| best.ghost_at(clock.elapsed));
+
+    let Some(path) = path else {
+        for (entity, _) in ghost_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+
+    if let Ok((_, mut transform)) = ghost_query.get_single_mut() {
+        *transform = path;
+        return;
+    }
+    let build = selections.0.first().cloned().unwrap_or_default();
+    commands.spawn((
+        path,
+        Visibility::default(),
+        Ghost,
+        StateScoped(Screen::Race),
+    )).with_children(|parent| {
+        // Same offset as KartVisual
+        parent.spawn((
+            SceneRoot(asset_server.load(GltfAssetLabel::Scene(0).from_asset(catalog.model(&build).to_string()))),
+            Transform::from_rotation(Quat::from_rotation_y(std::f32::consts::PI))
+                .with_scale(Vec3::splat(0.01)),
+        ));
+    });
+}
+
+// Race time and the last split, top center of player 1's screen
+fn spawn_time_trial_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<(Entity, &FollowCamera)>,
+) {
+    let Some((camera, _)) = camera_query.iter().find(|(_, follow)| follow.player == 0) else { return; };
+    let font = asset_server.load("fonts/HK.ttf");
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(30.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            TargetCamera(camera),
+            StateScoped(Screen::Race),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont { font: font.clone(), font_size: 56.0, ..default() },
+                TextColor(Color::WHITE),
+                TimeTrialClock,
+            ));
+            parent.spawn((
+                Text::new(""),
+                TextFont { font: font.clone(), font_size: 40.0, ..default() },
+                TextColor(Color::WHITE),
+                SplitText,
+            ));
+        });
+}
+
+fn update_time_trial_hud(
+    time_trial: Res<TimeTrial>,
+    kart_query: Query<(&LocalPlayer, &PlayerStats)>,
+    clock: Res<RaceClock>,
+    mut clock_query: Query<&mut Text, (With<TimeTrialClock>, Without<SplitText>)>,
+    mut split_query: Query<(&mut Text, &mut TextColor), With<SplitText>>,
+) {
+    let penalty = kart_query.iter().find(|(local, _)| local.index == 0).map_or(0.0, |(_, stats)| stats.penalty);
+    // Stopped on the line
+    let shown_time = if time_trial.run.total > 0.0 { time_trial.run.total } else { clock.elapsed + penalty };
+    for mut text in clock_query.iter_mut() {
+        text.0 = format_time(shown_time);
+    }
+
+    for (mut text, mut color) in split_query.iter_mut() {
+        match time_trial.split {
+            Some((diff, shown_for)) if shown_for < SPLIT_SHOWN_FOR => {
+                text.0 = format_split(diff);
+                // Blue when ahead of the best, red when behind
+                color.0 = if diff < 0.0 { Color::srgb(0.3, 0.7, 1.0) } else { Color::srgb(1.0, 0.3, 0.2) };
+            }
+            _ => text.0.clear(),
+        }
+    }
+}
+
+fn open_results(
+    mut time_trial: ResMut<TimeTrial>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    time: Res<Time>,
+) {
+    if time_trial.run.total <= 0.0 {
+        return;
+    }
+    time_trial.finished_for += time.delta_secs();
+    if time_trial.finished_for >= RESULTS_DELAY {
+        next_screen.set(Screen::Results);
+    }
+}
+
+fn ghost_label(ghost: bool) -> String {
+    format!("Ghost: < {} >", if ghost { "On" } else { "Off" })
+}
+
+fn spawn_results(mut commands: Commands, asset_server: Res<AssetServer>, time_trial: Res<TimeTrial>) {
+    let font = asset_server.load("fonts/HK.ttf");
+    let text = |size: f32| TextFont { font: font.clone(), font_size: size, ..default() };
+    let run = &time_trial.run;
+    let best = time_trial.best.as_ref();
+
+    let camera = commands.spawn((
+        Camera2d,
+        Camera { order: 50, clear_color: ClearColorConfig::None, ..default() },
+        StateScoped(Screen::Results),
+    )).id();
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.75)),
+            TargetCamera(camera),
+            StateScoped(Screen::Results),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(format_time(run.total)),
+                text(80.0),
+                TextColor(Color::srgb(1.0, 0.85, 0.0)),
+            ));
+
+            // `best` is the record this run was raced against
+            let verdict = match best {
+                None => "First record!".to_string(),
+                Some(best) if time_trial.new_record => format!("New record! {} faster", format_time(best.total - run.total)),
+                Some(best) => format!("Best: {}  ({})", format_time(best.total), format_split(run.total - best.total)),
+            };
+            parent.spawn((Text::new(verdict), text(36.0), TextColor(Color::WHITE)));
+
+            for (i, split) in run.splits.iter().enumerate() {
+                let diff = best.and_then(|best| best.splits.get(i)).map(|best| format!("  {}", format_split(split - best)));
+                parent.spawn((
+                    Text::new(format!("Split {}:  {}{}", i + 1, format_time(*split), diff.unwrap_or_default())),
+                    text(22.0),
+                    TextColor(Color::srgb(0.85, 0.85, 0.85)),
+                ));
+            }
+
+            parent.spawn((
+                Text::new(ghost_label(time_trial.ghost)),
+                text(32.0),
+                TextColor(Color::WHITE),
+                Node { margin: UiRect::top(Val::Px(20.0)), ..default() },
+                ResultsGhostLabel,
+            ));
+            parent.spawn((
+                Text::new("Left/Right: ghost   Enter: retry   Esc: main menu"),
+                text(22.0),
+                TextColor(Color::srgb(0.8, 0.8, 0.8)),
+            ));
+        });
+}
+
+fn results_input(
+    menu: Res<MenuInput>,
+    mut time_trial: ResMut<TimeTrial>,
+    mut label_query: Query<&mut Text, With<ResultsGhostLabel>>,
+    mut next_screen: ResMut<NextState<Screen>>,
+    mut next_phase: ResMut<NextState<RacePhase>>,
+) {
+    if menu.left || menu.right {
+        time_trial.ghost = !time_trial.ghost;
+        for mut text in label_query.iter_mut() {
+            text.0 = ghost_label(time_trial.ghost);
+        }
+    }
+    if menu.confirm {
+        next_screen.set(Screen::Race);
+        next_phase.set(RacePhase::Countdown);
+    }
+    if menu.back {
+        next_screen.set(Screen::MainMenu);
+    }
+}
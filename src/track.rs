@@ -1,23 +1,598 @@
 use bevy::prelude::*;
-use bevy::gltf::GltfAssetLabel;
+use bevy::gltf::{GltfAssetLabel, GltfExtras};
 use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 
 pub struct TrackPlugin;
 
 impl Plugin for TrackPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_track);
+        add_track(app, DEFAULT_TRACK);
     }
 }
 
-fn spawn_track(mut commands: Commands, asset_server: Res<AssetServer>) {
+// Races outside the Grand Prix
+pub const DEFAULT_TRACK: &str = "paris_bis";
+
+// Every track, with its definition and racing line baked in for the web build
+const TRACK_FILES: [(&str, &str); 4] = [
+    ("promenade", include_str!("../assets/tracks/promenade.json")),
+    ("paris_bis", include_str!("../assets/tracks/paris_bis.json")),
+    ("flat", include_str!("../assets/tracks/flat.json")),
+    ("arena", include_str!("../assets/tracks/arena.json")),
+];
+
+const SPLINE_FILES: [(&str, &str); 4] = [
+    ("SPLINE.json", include_str!("../assets/SPLINE.json")),
+    ("CurvedPath.json", include_str!("../assets/CurvedPath.json")),
+    ("tracks/flat_spline.json", include_str!("../assets/tracks/flat_spline.json")),
+    ("tracks/arena_spline.json", include_str!("../assets/tracks/arena_spline.json")),
+];
+
+// Tracks to race on, not the headless test slab or the battle arena
+pub fn race_tracks() -> Vec<&'static str> {
+    TRACK_FILES.iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !["flat", "arena"].contains(name))
+        .collect()
+}
+
+pub fn track_definition(name: &str) -> Option<TrackDefinition> {
+    let (_, embedded) = TRACK_FILES.iter().find(|(file, _)| *file == name)?;
+    Some(crate::config::load(&format!("tracks/{}.json", name), embedded))
+}
+
+// Starts on `track`, LoadTrack switches to another one
+fn add_track(app: &mut App, track: &str) {
+    let definition = track_definition(track).unwrap_or_else(|| panic!("Unknown track {}", track));
+    app.insert_resource(definition.spline())
+       .insert_resource(definition)
+       .init_resource::<TrackVariant>()
+       .add_event::<LoadTrack>()
+       .add_systems(Update, (
+           load_track,
+           (spawn_track, spawn_walls, spawn_kill_volumes).run_if(resource_changed::<TrackDefinition>),
+           tag_track_nodes,
+           make_kill_volume_sensors,
+           fix_mirrored_winding,
+       ).chain());
+}
+
+// Unloads whatever is on the current track and swaps in the named one (see TrackEntity),
+// a variant suffix such as "_reverse" lays it out that way
+#[derive(Event)]
+pub struct LoadTrack(pub String);
+
+// Belongs to the current track: despawned when another one is loaded
+#[derive(Component)]
+pub struct TrackEntity;
+
+// How the next track is laid out: flipped left to right, and/or driven the other way round.
+// Applied when a track is loaded, the current one keeps its own in TrackDefinition::variant.
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct TrackVariant {
+    pub mirror: bool,
+    pub reverse: bool,
+}
+
+impl TrackVariant {
+    // Appended to save keys so records on a variant don't mix with the normal track
+    pub fn suffix(&self) -> &'static str {
+        match (self.mirror, self.reverse) {
+            (false, false) => "",
+            (true, false) => "_mirror",
+            (false, true) => "_reverse",
+            (true, true) => "_mirror_reverse",
+        }
+    }
+
+    // "promenade_reverse" is promenade driven the other way round, on top of the chosen variant
+    pub fn from_name(name: &str) -> (&str, Self) {
+        for variant in [Self { mirror: true, reverse: true }, Self { mirror: true, reverse: false }, Self { mirror: false, reverse: true }] {
+            if let Some(track) = name.strip_suffix(variant.suffix()) {
+                return (track, variant);
+            }
+        }
+        (name, Self::default())
+    }
+}
+
+fn load_track(
+    mut commands: Commands,
+    mut load_events: EventReader<LoadTrack>,
+    entity_query: Query<Entity, With<TrackEntity>>,
+    variant: Res<TrackVariant>,
+) {
+    let Some(LoadTrack(name)) = load_events.read().last() else { return; };
+    let (name, named) = TrackVariant::from_name(name);
+    let Some(definition) = track_definition(name) else {
+        error!("Unknown track {}", name);
+        return;
+    };
+    let definition = definition.with_variant(TrackVariant { mirror: variant.mirror || named.mirror, reverse: variant.reverse || named.reverse });
+
+    for entity in entity_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    info!("Loading track {} {:?}", definition.name, definition.variant);
+    commands.insert_resource(definition.spline());
+    commands.insert_resource(definition);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum SurfaceKind {
+    #[default]
+    Road,
+    // Grass, sand... slows down anyone not boosting
+    Offroad,
+    BoostPad,
+    // Launches the kart and allows tricks
+    Ramp,
+}
+
+impl SurfaceKind {
+    // From a glTF node name such as "BoostPad.002" or "grass_left", or an extras value
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if name.contains("boost") {
+            Some(SurfaceKind::BoostPad)
+        } else if name.contains("ramp") {
+            Some(SurfaceKind::Ramp)
+        } else if ["offroad", "off_road", "grass", "sand"].iter().any(|k| name.contains(k)) {
+            Some(SurfaceKind::Offroad)
+        } else if name.contains("road") {
+            Some(SurfaceKind::Road)
+        } else {
+            None
+        }
+    }
+}
+
+// Surface of a piece of track geometry, found from its glTF node
+#[derive(Component)]
+pub struct Surface(pub SurfaceKind);
+
+// Box of the track with a given surface, for tracks whose model isn't tagged
+#[derive(Deserialize, Clone)]
+pub struct SurfaceRegion {
+    pub kind: SurfaceKind,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+// Water, pits, holes in the walls: touching one calls Lakitu
+#[derive(Deserialize, Clone)]
+pub struct KillRegion {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+#[derive(Component)]
+pub struct KillVolume;
+
+// Solid box added to the track, for arenas and tracks without a model
+#[derive(Deserialize, Clone)]
+pub struct WallRegion {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+// Everything about a track that isn't in its model, from `assets/tracks/<name>.json`
+#[derive(Resource, Deserialize, Clone)]
+pub struct TrackDefinition {
+    pub name: String,
+    // Empty for the procedural flat track
+    pub model: String,
+    // Racing line file, relative to assets
+    #[serde(default)]
+    pub spline: Option<String>,
+    #[serde(default = "default_checkpoints")]
+    pub checkpoints: Vec<[f32; 3]>,
+    // Half extents of every checkpoint gate, narrow tracks need them smaller than the default
+    #[serde(default = "default_checkpoint_size")]
+    pub checkpoint_size: [f32; 3],
+    #[serde(default)]
+    pub grid: StartGrid,
+    #[serde(default = "default_item_boxes")]
+    pub item_boxes: Vec<[f32; 3]>,
+    #[serde(default = "default_coins")]
+    pub coins: Vec<[f32; 3]>,
+    #[serde(default)]
+    pub surfaces: Vec<SurfaceRegion>,
+    #[serde(default)]
+    pub kill_volumes: Vec<KillRegion>,
+    #[serde(default)]
+    pub walls: Vec<WallRegion>,
+    // Seconds before a broken item box comes back, never when unset
+    #[serde(default)]
+    pub item_box_respawn: Option<f32>,
+    // Last resort for anything that slips through the kill volumes
+    #[serde(default = "default_fall_height")]
+    pub fall_height: f32,
+    // Set by with_variant, everything above is already flipped/reordered to match
+    #[serde(skip)]
+    pub variant: TrackVariant,
+}
+
+// Where karts line up: slot 0 at `position`, the next ones across then behind it
+#[derive(Deserialize, Clone)]
+pub struct StartGrid {
+    pub position: [f32; 3],
+    // Degrees around Y, 0 faces -Z
+    pub yaw: f32,
+    pub columns: usize,
+    // Between slots across the grid and between rows
+    pub spacing: [f32; 2],
+}
+
+impl Default for StartGrid {
+    fn default() -> Self {
+        Self { position: [0.0, 2.0, 0.0], yaw: 0.0, columns: 4, spacing: [2.5, 2.0] }
+    }
+}
+
+impl StartGrid {
+    pub fn slot(&self, index: usize) -> (Vec3, Quat) {
+        let rot = Quat::from_rotation_y(self.yaw.to_radians());
+        let across = (index % self.columns.max(1)) as f32 * self.spacing[0];
+        let behind = (index / self.columns.max(1)) as f32 * self.spacing[1];
+        (Vec3::from(self.position) + rot * Vec3::new(across, 0.0, behind), rot)
+    }
+}
+
+fn default_fall_height() -> f32 {
+    -10.0
+}
+
+// The layout every track used before they had their own
+fn default_checkpoints() -> Vec<[f32; 3]> {
+    vec![[10.0, 0.0, 0.0], [0.0, 0.0, 10.0], [-10.0, 0.0, 0.0], [0.0, 0.0, -10.0]]
+}
+
+fn default_checkpoint_size() -> [f32; 3] {
+    [10.0, 10.0, 1.0]
+}
+
+fn default_item_boxes() -> Vec<[f32; 3]> {
+    vec![[5.0, 1.0, 5.0], [5.0, 1.0, -5.0], [-5.0, 1.0, 5.0]]
+}
+
+fn default_coins() -> Vec<[f32; 3]> {
+    vec![[3.0, 1.0, 0.0], [-3.0, 1.0, 0.0], [0.0, 1.0, 3.0], [0.0, 1.0, -3.0]]
+}
+
+// Mirroring is about the X = 0 plane, so boxes swap their min and max X
+fn mirror_x(p: [f32; 3]) -> [f32; 3] {
+    [-p[0], p[1], p[2]]
+}
+
+fn mirror_box(min: &mut [f32; 3], max: &mut [f32; 3]) {
+    let (min_x, max_x) = (min[0], max[0]);
+    min[0] = -max_x;
+    max[0] = -min_x;
+}
+
+impl TrackDefinition {
+    // Flips the layout for a mirror run; reverse keeps checkpoint 0 as the finish line and
+    // takes the others backwards, so the lap logic counts them in the opposite direction.
+    // The grid moves across the line so karts still cross it first.
+    pub fn with_variant(mut self, variant: TrackVariant) -> Self {
+        if variant.mirror {
+            for pos in self.checkpoints.iter_mut().chain(self.item_boxes.iter_mut()).chain(self.coins.iter_mut()) {
+                *pos = mirror_x(*pos);
+            }
+            for region in &mut self.surfaces {
+                mirror_box(&mut region.min, &mut region.max);
+            }
+            for region in &mut self.kill_volumes {
+                mirror_box(&mut region.min, &mut region.max);
+            }
+            for region in &mut self.walls {
+                mirror_box(&mut region.min, &mut region.max);
+            }
+            self.grid.position = mirror_x(self.grid.position);
+            self.grid.yaw = -self.grid.yaw;
+            self.grid.spacing[0] = -self.grid.spacing[0];
+        }
+        if variant.reverse && self.checkpoints.len() > 1 {
+            self.checkpoints[1..].reverse();
+        }
+        if variant.reverse {
+            // Same slots on the other side of the start line, facing it
+            let (pos, rot) = self.grid.slot(0);
+            let forward = rot * Vec3::NEG_Z;
+            let to_line = self.checkpoints.first().map_or(0.0, |line| (Vec3::from(*line) - pos).dot(forward));
+            self.grid.position = (pos + forward * 2.0 * to_line).to_array();
+            self.grid.yaw += 180.0;
+            self.grid.spacing[0] = -self.grid.spacing[0];
+        }
+        self.variant = variant;
+        self
+    }
+
+    // From the spline file, or straight through the checkpoints without one
+    pub fn spline(&self) -> TrackSpline {
+        let embedded = self.spline.as_deref()
+            .and_then(|path| SPLINE_FILES.iter().find(|(file, _)| *file == path));
+        match (&self.spline, embedded) {
+            (Some(path), Some((_, json))) => {
+                let file: SplineFile = crate::config::load(path, json);
+                let mut points: Vec<Vec3> = file.points.into_iter()
+                    .map(|p| Vec3::from(if self.variant.mirror { mirror_x([p.x, p.y, p.z]) } else { [p.x, p.y, p.z] }))
+                    .collect();
+                if self.variant.reverse {
+                    points.reverse();
+                }
+                TrackSpline::new(points)
+            }
+            _ => TrackSpline::new(self.checkpoints.iter().map(|c| Vec3::from(*c)).collect()),
+        }
+    }
+
+    pub fn surface_at(&self, pos: Vec3) -> Option<SurfaceKind> {
+        self.surfaces.iter()
+            .find(|region| pos.cmpge(Vec3::from(region.min)).all() && pos.cmple(Vec3::from(region.max)).all())
+            .map(|region| region.kind)
+    }
+}
+
+#[derive(Component)]
+pub struct TrackRoot;
+
+// Procedural flat track for headless runs and tests: a ground slab and a diamond racing line
+// through the checkpoints spawned by LogicPlugin, no GLB to load
+pub struct FlatTrackPlugin;
+
+impl Plugin for FlatTrackPlugin {
+    fn build(&self, app: &mut App) {
+        add_track(app, "flat");
+    }
+}
+
+pub const FLAT_TRACK_SIZE: f32 = 100.0;
+
+// Ground slab of the flat track, walls come from the definition.
+// Meshes only when rendering, headless runs have no mesh assets.
+fn spawn_flat_track(
+    commands: &mut Commands,
+    meshes: &mut Option<ResMut<Assets<Mesh>>>,
+    materials: &mut Option<ResMut<Assets<StandardMaterial>>>,
+) {
+    let ground = commands.spawn((
+        Transform::from_xyz(0.0, -0.5, 0.0),
+        Collider::cuboid(FLAT_TRACK_SIZE / 2.0, 0.5, FLAT_TRACK_SIZE / 2.0),
+        RigidBody::Fixed,
+        Name::new("Flat track"),
+        TrackEntity,
+    )).id();
+    if let (Some(meshes), Some(materials)) = (meshes.as_mut(), materials.as_mut()) {
+        commands.entity(ground).insert((
+            Mesh3d(meshes.add(Cuboid::new(FLAT_TRACK_SIZE, 1.0, FLAT_TRACK_SIZE))),
+            MeshMaterial3d(materials.add(StandardMaterial { base_color: Color::srgb(0.35, 0.35, 0.38), ..default() })),
+        ));
+    }
+}
+
+// Racing line of the track, used to measure how far along the lap a kart is
+#[derive(Resource)]
+pub struct TrackSpline {
+    pub points: Vec<Vec3>,
+    // Arc length at each point, closing segment included
+    distances: Vec<f32>,
+    pub length: f32,
+}
+
+#[derive(Deserialize)]
+struct SplineFile {
+    points: Vec<SplinePoint>,
+}
+
+#[derive(Deserialize)]
+struct SplinePoint {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+impl TrackSpline {
+    pub fn new(points: Vec<Vec3>) -> Self {
+        let mut distances = Vec::with_capacity(points.len() + 1);
+        let mut length = 0.0;
+        for i in 0..points.len() {
+            distances.push(length);
+            length += points[i].distance(points[(i + 1) % points.len()]);
+        }
+        distances.push(length);
+
+        Self { points, distances, length }
+    }
+
+    // Distance along the spline of the closest point to `pos`
+    pub fn project(&self, pos: Vec3) -> f32 {
+        let mut best_dist = f32::MAX;
+        let mut best_s = 0.0;
+        for i in 0..self.points.len() {
+            let a = self.points[i];
+            let b = self.points[(i + 1) % self.points.len()];
+            let ab = b - a;
+            let t = if ab.length_squared() > 0.0 { ((pos - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0) } else { 0.0 };
+            let dist = pos.distance_squared(a + ab * t);
+            if dist < best_dist {
+                best_dist = dist;
+                best_s = self.distances[i] + ab.length() * t;
+            }
+        }
+        best_s
+    }
+
+    // Position and forward tangent at distance `s` along the spline
+    pub fn sample(&self, s: f32) -> (Vec3, Vec3) {
+        if self.points.len() < 2 {
+            return (self.points.first().copied().unwrap_or_default(), Vec3::NEG_Z);
+        }
+        let s = s.rem_euclid(self.length);
+        let i = self.distances.partition_point(|d| *d <= s).saturating_sub(1).min(self.points.len() - 1);
+        let a = self.points[i];
+        let b = self.points[(i + 1) % self.points.len()];
+        let seg = self.distances[i + 1] - self.distances[i];
+        let t = if seg > 0.0 { (s - self.distances[i]) / seg } else { 0.0 };
+        (a.lerp(b, t), (b - a).normalize_or(Vec3::NEG_Z))
+    }
+
+    // Forward distance from `from` to `to`, wrapping around the lap
+    pub fn delta(&self, from: f32, to: f32) -> f32 {
+        (to - from).rem_euclid(self.length)
+    }
+}
+
+fn spawn_track(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    definition: Res<TrackDefinition>,
+    mut meshes: Option<ResMut<Assets<Mesh>>>,
+    mut materials: Option<ResMut<Assets<StandardMaterial>>>,
+) {
+    if definition.model.is_empty() {
+        spawn_flat_track(&mut commands, &mut meshes, &mut materials);
+        return;
+    }
+
     // Load the GLB track
-    let track_handle = asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/tracks/paris-bis.glb"));
+    let track_handle = asset_server.load(GltfAssetLabel::Scene(0).from_asset(definition.model.clone()));
+
+    // Mirror mode flips the whole scene, fix_mirrored_winding turns its colliders the right way out
+    let scale = if definition.variant.mirror { Vec3::new(-1.0, 1.0, 1.0) } else { Vec3::splat(1.0) };
 
     commands.spawn((
         SceneRoot(track_handle),
-        Transform::from_xyz(0.0, 0.0, 0.0).with_scale(Vec3::splat(1.0)), // Taille réelle selon utilisateur
-        AsyncCollider(ComputedColliderShape::TriMesh(TriMeshFlags::default())), // Génère le collider précis pour le circuit
+        Transform::from_xyz(0.0, 0.0, 0.0).with_scale(scale), // Taille réelle selon utilisateur
+        // Trimesh colliders for every mesh of the scene once it's loaded (the suspension rays need them)
+        AsyncSceneCollider {
+            shape: Some(ComputedColliderShape::TriMesh(TriMeshFlags::default())),
+            ..default()
+        },
         RigidBody::Fixed,
+        TrackRoot,
+        TrackEntity,
     ));
 }
+
+fn spawn_walls(
+    mut commands: Commands,
+    definition: Res<TrackDefinition>,
+    mut meshes: Option<ResMut<Assets<Mesh>>>,
+    mut materials: Option<ResMut<Assets<StandardMaterial>>>,
+) {
+    for region in &definition.walls {
+        let (min, max) = (Vec3::from(region.min), Vec3::from(region.max));
+        let half = (max - min) / 2.0;
+        let wall = commands.spawn((
+            Transform::from_translation(min + half),
+            Collider::cuboid(half.x, half.y, half.z),
+            RigidBody::Fixed,
+            Name::new("Wall"),
+            TrackEntity,
+        )).id();
+        if let (Some(meshes), Some(materials)) = (meshes.as_mut(), materials.as_mut()) {
+            commands.entity(wall).insert((
+                Mesh3d(meshes.add(Cuboid::from_size(half * 2.0))),
+                MeshMaterial3d(materials.add(StandardMaterial { base_color: Color::srgb(0.8, 0.25, 0.2), ..default() })),
+            ));
+        }
+    }
+}
+
+fn spawn_kill_volumes(mut commands: Commands, definition: Res<TrackDefinition>) {
+    for region in &definition.kill_volumes {
+        let (min, max) = (Vec3::from(region.min), Vec3::from(region.max));
+        let half = (max - min) / 2.0;
+        commands.spawn((
+            Transform::from_translation(min + half),
+            Collider::cuboid(half.x, half.y, half.z),
+            Sensor,
+            KillVolume,
+            Name::new("Kill volume"),
+            TrackEntity,
+        ));
+    }
+}
+
+// Track nodes named after a surface (or with `{"surface": "offroad"}` in their extras) get a Surface once the scene spawns,
+// nodes named like water/void/kill become kill volumes
+fn tag_track_nodes(
+    mut commands: Commands,
+    node_query: Query<(Entity, &Name, Option<&GltfExtras>), Added<Name>>,
+    parent_query: Query<&Parent>,
+    root_query: Query<(), With<TrackRoot>>,
+) {
+    for (entity, name, extras) in node_query.iter() {
+        if !parent_query.iter_ancestors(entity).any(|ancestor| root_query.contains(ancestor)) {
+            continue;
+        }
+
+        let from_extras = extras
+            .and_then(|extras| serde_json::from_str::<serde_json::Value>(&extras.value).ok())
+            .and_then(|value| value.get("surface")?.as_str().and_then(SurfaceKind::parse));
+        let lower = name.as_str().to_lowercase();
+        if ["kill", "water", "void"].iter().any(|k| lower.contains(k)) {
+            commands.entity(entity).insert(KillVolume);
+        } else if let Some(kind) = from_extras.or_else(|| SurfaceKind::parse(name.as_str())) {
+            commands.entity(entity).insert(Surface(kind));
+        }
+    }
+}
+
+// Kill volume meshes get their trimesh from AsyncSceneCollider like the rest of the track, but nothing should drive on them
+fn make_kill_volume_sensors(
+    mut commands: Commands,
+    collider_query: Query<Entity, Added<Collider>>,
+    parent_query: Query<&Parent>,
+    kill_query: Query<(), With<KillVolume>>,
+) {
+    for entity in collider_query.iter() {
+        if is_kill_volume(entity, &kill_query, &parent_query) {
+            commands.entity(entity).insert(Sensor);
+        }
+    }
+}
+
+// A negative scale turns every triangle of the scene inside out: AsyncSceneCollider builds the trimesh from
+// the unflipped mesh and Rapier only scales its vertices, so swap two corners of each triangle to restore the winding.
+// Rapier may already have applied the mirrored scale to the shape, the rebuild starts from the unscaled vertices
+// and gets the same scale applied again.
+fn fix_mirrored_winding(
+    mut commands: Commands,
+    collider_query: Query<(Entity, &Collider), Added<Collider>>,
+    parent_query: Query<&Parent>,
+    root_query: Query<(), With<TrackRoot>>,
+    definition: Res<TrackDefinition>,
+) {
+    if !definition.variant.mirror {
+        return;
+    }
+    for (entity, collider) in collider_query.iter() {
+        if !parent_query.iter_ancestors(entity).any(|ancestor| root_query.contains(ancestor)) {
+            continue;
+        }
+        let Some(trimesh) = collider.as_trimesh() else { continue; };
+
+        let scale = collider.scale();
+        let vertices: Vec<Vec3> = trimesh.raw.vertices().iter().map(|v| Vec3::new(v.x, v.y, v.z) / scale).collect();
+        let indices: Vec<[u32; 3]> = trimesh.raw.indices().iter().map(|[a, b, c]| [*a, *c, *b]).collect();
+        commands.entity(entity).insert(Collider::trimesh_with_flags(vertices, indices, trimesh.raw.flags()));
+    }
+}
+
+pub fn is_kill_volume(entity: Entity, kill_query: &Query<(), With<KillVolume>>, parent_query: &Query<&Parent>) -> bool {
+    std::iter::once(entity)
+        .chain(parent_query.iter_ancestors(entity))
+        .any(|e| kill_query.contains(e))
+}
+
+// Surface a collider belongs to: its own node or the closest tagged ancestor (glTF meshes sit under their node)
+pub fn surface_of(entity: Entity, surface_query: &Query<&Surface>, parent_query: &Query<&Parent>) -> Option<SurfaceKind> {
+    std::iter::once(entity)
+        .chain(parent_query.iter_ancestors(entity))
+        .find_map(|e| surface_query.get(e).ok())
+        .map(|surface| surface.0)
+}
@@ -1,103 +1,273 @@
 use bevy::prelude::*;
-use crate::player::Kart;
-use crate::logic::PlayerStats;
+use crate::player::{FollowCamera, Kart, LocalPlayers, Respawn};
+use crate::logic::{Countdown, GameMode, PlayerStats, RaceClock, RaceConfig, RacePhase, RacePosition};
+use crate::items::HeldItem;
+use crate::time_trial::format_time;
 
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_ui)
-           .add_systems(Update, update_ui);
+        app.add_systems(Update, (setup_ui, update_ui, update_countdown_text, update_respawn_fade));
     }
 }
 
+// Every HUD element points at the kart it displays
 #[derive(Component)]
-struct HudText;
+struct HudText(Entity);
 
 #[derive(Component)]
-struct ItemIcon;
-
-fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands
-        .spawn((
-            Node {
-                width: Val::Percent(100.0),
-                height: Val::Percent(100.0),
-                align_items: AlignItems::FlexEnd,
-                justify_content: JustifyContent::SpaceBetween,
-                padding: UiRect::all(Val::Px(30.0)),
-                ..default()
-            },
-        ))
-        .with_children(|parent| {
-            // Stats (Left)
-            parent.spawn((
-                Text::new(""),
-                TextFont {
-                    font_size: 45.0,
-                    ..default()
-                },
-                TextColor(Color::WHITE),
-                HudText,
-            ));
+struct ItemIcon(Entity);
+
+// Uses left of a multi-use item, in the item box corner
+#[derive(Component)]
+struct ItemCount(Entity);
+
+#[derive(Component)]
+struct PositionText(Entity);
+
+#[derive(Component)]
+struct CountdownText;
 
-            // Item Box (Right)
-            parent.spawn((
+#[derive(Component)]
+struct RespawnFade(Entity);
+
+// One HUD per local player, rendered into that player's camera viewport
+fn setup_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<(Entity, &FollowCamera), Added<FollowCamera>>,
+    local_players: Res<LocalPlayers>,
+) {
+    // Smaller HUD when the screen is split
+    let scale = if local_players.0 > 1 { 0.6 } else { 1.0 };
+
+    for (camera, follow) in camera_query.iter() {
+        let kart = follow.target;
+
+        commands
+            .spawn((
                 Node {
-                    width: Val::Px(120.0),
-                    height: Val::Px(120.0),
-                    border: UiRect::all(Val::Px(5.0)),
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::FlexEnd,
+                    justify_content: JustifyContent::SpaceBetween,
+                    padding: UiRect::all(Val::Px(30.0 * scale)),
                     ..default()
                 },
-                BorderColor(Color::srgb(0.8, 0.8, 0.0)),
-                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
-            )).with_children(|box_parent| {
-                box_parent.spawn((
-                    ImageNode::new(asset_server.load("images/mushroom.png")), // Hidden placeholder
+                TargetCamera(camera),
+            ))
+            .with_children(|parent| {
+                // Race position (Top right)
+                parent.spawn((
+                    Text::new("1st"),
+                    TextFont {
+                        font: asset_server.load("fonts/HK.ttf"),
+                        font_size: 90.0 * scale,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(1.0, 0.85, 0.0)),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(30.0 * scale),
+                        right: Val::Px(40.0 * scale),
+                        ..default()
+                    },
+                    PositionText(kart),
+                ));
+
+                // Countdown (Center)
+                parent.spawn((
+                    Text::new(""),
+                    TextFont {
+                        font: asset_server.load("fonts/HK.ttf"),
+                        font_size: 160.0 * scale,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
                     Node {
-                        width: Val::Percent(90.0),
-                        height: Val::Percent(90.0),
-                        display: Display::None, // Hide by default
+                        position_type: PositionType::Absolute,
+                        top: Val::Percent(30.0),
+                        width: Val::Percent(100.0),
+                        justify_content: JustifyContent::Center,
                         ..default()
                     },
-                    ItemIcon,
+                    TextLayout::new_with_justify(JustifyText::Center),
+                    CountdownText,
+                ));
+
+                // Stats (Left)
+                parent.spawn((
+                    Text::new(""),
+                    TextFont {
+                        font_size: 45.0 * scale,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                    HudText(kart),
+                ));
+
+                // Item Box (Right)
+                parent.spawn((
+                    Node {
+                        width: Val::Px(120.0 * scale),
+                        height: Val::Px(120.0 * scale),
+                        border: UiRect::all(Val::Px(5.0)),
+                        ..default()
+                    },
+                    BorderColor(Color::srgb(0.8, 0.8, 0.0)),
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+                )).with_children(|box_parent| {
+                    box_parent.spawn((
+                        ImageNode::new(asset_server.load("images/mushroom.png")), // Hidden placeholder
+                        Node {
+                            width: Val::Percent(90.0),
+                            height: Val::Percent(90.0),
+                            display: Display::None, // Hide by default
+                            ..default()
+                        },
+                        ItemIcon(kart),
+                    ));
+                    box_parent.spawn((
+                        Text::new(""),
+                        TextFont {
+                            font: asset_server.load("fonts/HK.ttf"),
+                            font_size: 36.0 * scale,
+                            ..default()
+                        },
+                        TextColor(Color::WHITE),
+                        Node {
+                            position_type: PositionType::Absolute,
+                            right: Val::Px(4.0),
+                            bottom: Val::Px(0.0),
+                            ..default()
+                        },
+                        ItemCount(kart),
+                    ));
+                });
+
+                // Black-out while Lakitu fishes the kart back (covers the whole viewport)
+                parent.spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(0.0),
+                        top: Val::Px(0.0),
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::NONE),
+                    RespawnFade(kart),
                 ));
             });
-        });
+    }
 }
 
 fn update_ui(
-    kart_query: Query<(&Kart, &PlayerStats)>,
-    mut text_query: Query<(&mut Text, &mut TextColor), With<HudText>>,
-    mut icon_query: Query<(&mut Node, &mut ImageNode), With<ItemIcon>>,
+    kart_query: Query<(&Kart, &PlayerStats, &RacePosition, &HeldItem)>,
+    mut text_query: Query<(&mut Text, &mut TextColor, &HudText), Without<PositionText>>,
+    mut position_query: Query<(&mut Text, &PositionText), Without<HudText>>,
+    mut icon_query: Query<(&mut Node, &mut ImageNode, &ItemIcon)>,
+    mut count_query: Query<(&mut Text, &ItemCount), (Without<HudText>, Without<PositionText>)>,
+    config: Res<RaceConfig>,
+    mode: Res<GameMode>,
     asset_server: Res<AssetServer>,
 ) {
-    if let Ok((kart, stats)) = kart_query.get_single() {
-        if let Ok((mut text, mut color)) = text_query.get_single_mut() {
+    for (mut text, owner) in position_query.iter_mut() {
+        if let Ok((_, _, position, _)) = kart_query.get(owner.0) {
+            text.0 = ordinal(position.0);
+        }
+    }
+
+    for (mut text, mut color, owner) in text_query.iter_mut() {
+        if let Ok((kart, stats, _, _)) = kart_query.get(owner.0) {
+            // Arenas have no laps, their own HUD shows the rest
+            let lap = if mode.is_arena() { String::new() } else { format!("LAP: {}/{}\n", stats.current_lap, config.laps) };
+            // Respawn penalties go on the race time
+            let penalty = if stats.penalty > 0.0 { format!("\nPENALTY: +{:.0}s", stats.penalty) } else { String::new() };
+            let finish = stats.finish_time.map_or(String::new(), |time| format!("\nTIME: {}", format_time(time)));
             text.0 = format!(
-                "LAP: {}/3\nCOINS: {}\nSPEED: {:.0} KM/H",
-                stats.current_lap,
+                "{}COINS: {}\nSPEED: {:.0} KM/H{}{}",
+                lap,
                 stats.coin_count,
-                (velocity_to_kmh(kart.speed)).abs()
+                (velocity_to_kmh(kart.speed)).abs(),
+                penalty,
+                finish
             );
-            
+
             if kart.is_boosting {
                 *color = TextColor(Color::srgb(1.0, 0.6, 0.0));
             } else {
                 *color = TextColor(Color::WHITE);
             }
         }
+    }
 
-        // Item Icon logic
-        if let Ok((mut node, mut image)) = icon_query.get_single_mut() {
-            if kart.is_boosting { // Visual hack for now: show mushroom when boosting
+    // Item Icon logic
+    for (mut node, mut image, owner) in icon_query.iter_mut() {
+        if let Ok((_, _, _, held)) = kart_query.get(owner.0) {
+            if let Some(item) = held.item {
+                let (path, tint) = item.icon();
                 node.display = Display::Flex;
-                image.image = asset_server.load("images/mushroom.png");
+                image.image = asset_server.load(path);
+                image.color = tint;
             } else {
                 node.display = Display::None;
             }
         }
     }
+
+    for (mut text, owner) in count_query.iter_mut() {
+        if let Ok((_, _, _, held)) = kart_query.get(owner.0) {
+            let label = if held.item.is_some() && held.uses > 1 { format!("x{}", held.uses) } else { String::new() };
+            if text.0 != label {
+                text.0 = label;
+            }
+        }
+    }
+}
+
+fn update_respawn_fade(
+    mut fade_query: Query<(&mut BackgroundColor, &RespawnFade)>,
+    respawn_query: Query<&Respawn>,
+) {
+    for (mut background, owner) in fade_query.iter_mut() {
+        let alpha = respawn_query.get(owner.0).map(|respawn| respawn.fade()).unwrap_or(0.0);
+        if background.0.alpha() != alpha {
+            background.0 = Color::BLACK.with_alpha(alpha);
+        }
+    }
+}
+
+fn update_countdown_text(
+    mut query: Query<&mut Text, With<CountdownText>>,
+    phase: Res<State<RacePhase>>,
+    countdown: Res<Countdown>,
+    clock: Res<RaceClock>,
+) {
+    let label = match phase.get() {
+        RacePhase::Lobby => String::new(),
+        RacePhase::Countdown => format!("{}", countdown.remaining.ceil().max(1.0) as u32),
+        RacePhase::Racing if clock.elapsed < 1.0 => "GO!".to_string(),
+        RacePhase::Racing => String::new(),
+    };
+
+    for mut text in query.iter_mut() {
+        if text.0 != label {
+            text.0 = label.clone();
+        }
+    }
+}
+
+pub fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (1, 11) | (2, 12) | (3, 13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
 }
 
 fn velocity_to_kmh(vel: f32) -> f32 {
@@ -0,0 +1,148 @@
+// Arena rounds on the headless app: hits are sent as KartHit events, the way items report them
+use bevy::prelude::*;
+use mariok_bevy::battle::{Balloons, BattleRound, START_BALLOONS};
+use mariok_bevy::headless::headless_app;
+use mariok_bevy::items::{Coin, HeldItem, Item, KartHit, OutOfRound};
+use mariok_bevy::logic::{GameMode, PlayerStats, RaceConfig, RacePhase};
+use mariok_bevy::menu::{MenuPlugin, Screen};
+use mariok_bevy::player::{spawn_kart, Kart};
+
+// Apart from each other and from the arena's walls and item boxes
+const SPOTS: [Vec3; 2] = [Vec3::new(4.0, 1.0, 4.0), Vec3::new(-4.0, 1.0, -4.0)];
+
+// Two karts in a round of `mode`, still in the lobby phase unless a test starts it
+fn arena_app(mode: GameMode) -> (App, [Entity; 2]) {
+    let mut app = headless_app(RaceConfig::default());
+    app.add_plugins(MenuPlugin);
+    // Balloon colours and the round HUD, the headless app has no renderer or text to add their assets
+    app.init_asset::<StandardMaterial>()
+       .init_asset::<Font>()
+       .init_asset::<Image>();
+    app.update();
+
+    let asset_server = app.world().resource::<AssetServer>().clone();
+    let karts = SPOTS.map(|pos| {
+        let world = app.world_mut();
+        let kart = spawn_kart(&mut world.commands(), &asset_server, pos, Quat::IDENTITY);
+        world.flush();
+        kart
+    });
+    *app.world_mut().resource_mut::<GameMode>() = mode;
+    app.world_mut().resource_mut::<NextState<Screen>>().set(Screen::Race);
+    step(&mut app, 3);
+    (app, karts)
+}
+
+fn step(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+fn hit(app: &mut App, kart: Entity) {
+    app.world_mut().send_event(KartHit { kart });
+    step(app, 1);
+}
+
+fn balloons(app: &App, kart: Entity) -> usize {
+    app.world().get::<Balloons>(kart).unwrap().count
+}
+
+fn count<T: Component>(app: &mut App) -> usize {
+    app.world_mut().query_filtered::<(), With<T>>().iter(app.world()).count()
+}
+
+#[test]
+fn a_hit_pops_one_balloon_then_protects_for_a_while() {
+    let (mut app, [kart, _]) = arena_app(GameMode::Battle);
+    assert_eq!(balloons(&app, kart), START_BALLOONS);
+
+    hit(&mut app, kart);
+    assert_eq!(balloons(&app, kart), START_BALLOONS - 1);
+    hit(&mut app, kart);
+    assert_eq!(balloons(&app, kart), START_BALLOONS - 1);
+
+    // Past the cooldown
+    step(&mut app, 150);
+    hit(&mut app, kart);
+    assert_eq!(balloons(&app, kart), START_BALLOONS - 2);
+}
+
+#[test]
+fn stars_protect_the_balloons() {
+    let (mut app, [kart, _]) = arena_app(GameMode::Battle);
+    app.world_mut().get_mut::<Kart>(kart).unwrap().star_timer = 5.0;
+
+    hit(&mut app, kart);
+    assert_eq!(balloons(&app, kart), START_BALLOONS);
+}
+
+#[test]
+fn last_kart_with_balloons_wins() {
+    let (mut app, [loser, winner]) = arena_app(GameMode::Battle);
+    app.world_mut().get_mut::<Balloons>(loser).unwrap().count = 1;
+    app.world_mut().get_mut::<HeldItem>(loser).unwrap().item = Some(Item::Banana);
+
+    hit(&mut app, loser);
+    step(&mut app, 1);
+    assert_eq!(balloons(&app, loser), 0);
+    assert!(app.world().get::<HeldItem>(loser).unwrap().item.is_none());
+    assert!(app.world().get::<OutOfRound>(loser).is_some());
+
+    let round = app.world().resource::<BattleRound>();
+    assert!(round.over);
+    assert_eq!(round.winner, Some(winner));
+}
+
+#[test]
+fn a_tie_when_time_runs_out_is_a_draw() {
+    let (mut app, _) = arena_app(GameMode::Battle);
+    app.world_mut().resource_mut::<NextState<RacePhase>>().set(RacePhase::Racing);
+    step(&mut app, 1);
+    app.world_mut().resource_mut::<BattleRound>().remaining = 0.01;
+    step(&mut app, 2);
+    let round = app.world().resource::<BattleRound>();
+    assert!(round.over);
+    assert_eq!(round.winner, None);
+
+    // One balloon down is a loss once time is up
+    let (mut app, [loser, winner]) = arena_app(GameMode::Battle);
+    hit(&mut app, loser);
+    app.world_mut().resource_mut::<NextState<RacePhase>>().set(RacePhase::Racing);
+    step(&mut app, 1);
+    app.world_mut().resource_mut::<BattleRound>().remaining = 0.01;
+    step(&mut app, 2);
+    let round = app.world().resource::<BattleRound>();
+    assert!(round.over);
+    assert_eq!(round.winner, Some(winner));
+}
+
+#[test]
+fn hits_drop_coins_around_the_kart() {
+    let (mut app, [kart, _]) = arena_app(GameMode::CoinRunners);
+    app.world_mut().get_mut::<PlayerStats>(kart).unwrap().coin_count = 5;
+    let coins = count::<Coin>(&mut app);
+
+    hit(&mut app, kart);
+    step(&mut app, 1);
+    assert_eq!(app.world().get::<PlayerStats>(kart).unwrap().coin_count, 2);
+    assert_eq!(count::<Coin>(&mut app), coins + 3);
+
+    // Nothing left to drop
+    app.world_mut().get_mut::<PlayerStats>(kart).unwrap().coin_count = 0;
+    hit(&mut app, kart);
+    step(&mut app, 1);
+    assert_eq!(count::<Coin>(&mut app), coins + 3);
+}
+
+#[test]
+fn coins_keep_appearing_while_racing() {
+    let (mut app, _) = arena_app(GameMode::CoinRunners);
+    let coins = count::<Coin>(&mut app);
+    step(&mut app, 120);
+    assert_eq!(count::<Coin>(&mut app), coins);
+
+    app.world_mut().resource_mut::<NextState<RacePhase>>().set(RacePhase::Racing);
+    step(&mut app, 120);
+    assert!(count::<Coin>(&mut app) > coins);
+}
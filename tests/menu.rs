@@ -0,0 +1,157 @@
+// Menu navigation on the headless app, driven by the same keyboard events a player would send
+use bevy::prelude::*;
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy_rapier3d::prelude::*;
+use mariok_bevy::controls::ControlsHelp;
+use mariok_bevy::grand_prix::{Cup, GrandPrix};
+use mariok_bevy::headless::headless_app;
+use mariok_bevy::logic::{GameMode, PlayerStats, RaceConfig};
+use mariok_bevy::menu::{MenuPlugin, Screen, Settings};
+use mariok_bevy::save::SaveDir;
+use mariok_bevy::player::{spawn_kart, LocalPlayer};
+use mariok_bevy::track::{track_definition, TrackDefinition, TrackVariant};
+
+// The menus' pictures and text, which the headless app has no renderer or text plugin to register
+fn add_menu(app: &mut App) {
+    app.init_asset::<Image>()
+       .init_asset::<Font>()
+       .init_asset::<StandardMaterial>()
+       .add_plugins(MenuPlugin);
+}
+
+fn menu_app() -> App {
+    let mut app = headless_app(RaceConfig::default());
+    add_menu(&mut app);
+    app.update();
+    // Headless apps save into a fresh directory, so this is always the first launch and the controls help opens
+    app.world_mut().resource_mut::<NextState<ControlsHelp>>().set(ControlsHelp::Hidden);
+    app.update();
+    app
+}
+
+fn key_event(key_code: KeyCode, logical_key: Key, state: ButtonState) -> KeyboardInput {
+    KeyboardInput { key_code, logical_key, state, repeat: false, window: Entity::PLACEHOLDER }
+}
+
+// One frame down, one frame up
+fn press(app: &mut App, key_code: KeyCode, logical_key: Key) {
+    app.world_mut().send_event(key_event(key_code, logical_key.clone(), ButtonState::Pressed));
+    app.update();
+    app.world_mut().send_event(key_event(key_code, logical_key, ButtonState::Released));
+    app.update();
+}
+
+fn step_frames(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+fn screen(app: &App) -> Screen {
+    *app.world().resource::<State<Screen>>().get()
+}
+
+#[test]
+fn back_from_settings_returns_to_the_main_menu() {
+    let mut app = menu_app();
+    let settings = app.world().resource::<Settings>().clone();
+
+    // Settings comes after the five modes
+    for _ in 0..5 {
+        press(&mut app, KeyCode::ArrowDown, Key::ArrowDown);
+    }
+    press(&mut app, KeyCode::Enter, Key::Enter);
+    assert_eq!(screen(&app), Screen::Settings);
+
+    // Up from the first row wraps around to Back
+    press(&mut app, KeyCode::ArrowUp, Key::ArrowUp);
+    press(&mut app, KeyCode::Enter, Key::Enter);
+    assert_eq!(screen(&app), Screen::MainMenu);
+
+    step_frames(&mut app, 3);
+    assert_eq!(screen(&app), Screen::MainMenu);
+    assert!(app.should_exit().is_none());
+    assert_eq!(*app.world().resource::<Settings>(), settings);
+}
+
+fn add_local_kart(app: &mut App, index: usize, pos: Vec3) -> Entity {
+    let asset_server = app.world().resource::<AssetServer>().clone();
+    let world = app.world_mut();
+    let kart = spawn_kart(&mut world.commands(), &asset_server, pos, Quat::IDENTITY);
+    world.flush();
+    world.entity_mut(kart).insert(LocalPlayer { index });
+    kart
+}
+
+// Through every checkpoint of the loaded track and back over the line
+fn drive_a_lap(app: &mut App, kart: Entity) {
+    let mut checkpoints = app.world().resource::<TrackDefinition>().checkpoints.clone();
+    checkpoints.push(checkpoints[0]);
+    for checkpoint in checkpoints {
+        let mut entity = app.world_mut().entity_mut(kart);
+        // Ride height above the racing line
+        entity.get_mut::<Transform>().unwrap().translation = Vec3::from(checkpoint) + Vec3::Y * 0.6;
+        *entity.get_mut::<Velocity>().unwrap() = Velocity::zero();
+        step_frames(app, 3);
+    }
+}
+
+#[test]
+fn grand_prix_awards_points_in_finishing_order() {
+    let mut app = headless_app(RaceConfig { laps: 1, ..default() });
+    add_menu(&mut app);
+    app.update();
+    *app.world_mut().resource_mut::<GameMode>() = GameMode::GrandPrix;
+    app.world_mut().resource_mut::<NextState<Screen>>().set(Screen::Race);
+    step_frames(&mut app, 3);
+    let cup = app.world().resource::<Cup>();
+    let (first_track, points) = (cup.tracks[0].clone(), cup.points.clone());
+    assert_eq!(app.world().resource::<TrackDefinition>().name, first_track);
+
+    let winner = add_local_kart(&mut app, 1, Vec3::new(0.0, 2.0, 0.0));
+    let runner_up = add_local_kart(&mut app, 0, Vec3::new(0.0, 2.0, 0.0));
+    step_frames(&mut app, 2);
+    drive_a_lap(&mut app, winner);
+    drive_a_lap(&mut app, runner_up);
+    assert!(app.world().get::<PlayerStats>(winner).unwrap().finished);
+    assert!(app.world().get::<PlayerStats>(runner_up).unwrap().finished);
+
+    // Standings once the finish has sunk in
+    step_frames(&mut app, 200);
+    assert_eq!(screen(&app), Screen::Standings);
+    let grand_prix = app.world().resource::<GrandPrix>();
+    assert_eq!(grand_prix.points, vec![points[1], points[0]]);
+    assert_eq!(grand_prix.last_points, vec![points[1], points[0]]);
+    assert_eq!(grand_prix.ranking(), vec![1, 0]);
+    assert!(grand_prix.last_times.iter().all(|time| time.is_some()));
+}
+
+#[test]
+fn the_cup_only_races_on_real_tracks() {
+    let app = menu_app();
+    let cup = app.world().resource::<Cup>();
+    assert!(cup.points.len() >= 4);
+    for race in &cup.tracks {
+        let (name, _) = TrackVariant::from_name(race);
+        let track = track_definition(name).unwrap_or_else(|| panic!("Unknown track {}", race));
+        assert!(!track.model.is_empty(), "{} has no model", race);
+        assert!(std::path::Path::new("assets").join(&track.model).exists(), "{} is missing {}", race, track.model);
+    }
+}
+
+#[test]
+fn controls_help_is_remembered_in_the_app_save_dir() {
+    let app = menu_app();
+    let save_dir = app.world().resource::<SaveDir>().0.clone();
+    assert!(save_dir.starts_with(std::env::temp_dir()));
+    assert!(save_dir.join("controls_seen.json").exists());
+
+    // A second app starts from an empty directory again
+    let mut other = headless_app(RaceConfig::default());
+    add_menu(&mut other);
+    other.update();
+    other.update();
+    assert_ne!(other.world().resource::<SaveDir>().0, save_dir);
+    assert_eq!(*other.world().resource::<State<ControlsHelp>>().get(), ControlsHelp::Shown);
+}
@@ -0,0 +1,550 @@
+// Race rules on the headless flat track: karts are teleported into checkpoints, coins and item boxes
+// and the real plugins (Rapier sensors included) decide what happens.
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use mariok_bevy::headless::{headless_app, ScriptedInput};
+use mariok_bevy::input::KartInput;
+use mariok_bevy::items::{BrokenItemBox, Coin, HeldItem, ItemBox};
+use mariok_bevy::logic::{FinishOrder, PlayerStats, RaceConfig, RacePhase};
+use mariok_bevy::player::{grid_transform, spawn_kart, Kart, KartBuild, KartCatalog, KartStats, Respawn, WallHit, RESPAWN_FADE_OUT, RESPAWN_PENALTY, RESPAWN_TIME};
+use mariok_bevy::time_trial::{GhostFrame, TimeTrialRecord};
+use mariok_bevy::track::{track_definition, LoadTrack, SurfaceKind, TrackDefinition, TrackRoot, TrackSpline, TrackVariant};
+
+// Ride height of the kart on its suspension
+const GROUND_Y: f32 = 0.6;
+
+// Well inside each checkpoint of LogicPlugin's layout, away from coins and item boxes
+const CHECKPOINTS: [Vec3; 4] = [
+    Vec3::new(15.0, GROUND_Y, 0.0),
+    Vec3::new(0.0, GROUND_Y, 10.0),
+    Vec3::new(-15.0, GROUND_Y, 0.0),
+    Vec3::new(0.0, GROUND_Y, -10.0),
+];
+
+// Ramp region of the flat track
+const RAMP: Vec3 = Vec3::new(-40.0, GROUND_Y, 30.0);
+
+// Open track, nothing to collide with
+const PARKING: Vec3 = Vec3::new(30.0, GROUND_Y, 30.0);
+
+fn race_app(laps: usize) -> App {
+    let mut app = headless_app(RaceConfig { laps, ..default() });
+    // Startup: track, checkpoints and gameplay objects
+    app.update();
+    app
+}
+
+fn add_kart(app: &mut App, pos: Vec3) -> Entity {
+    let asset_server = app.world().resource::<AssetServer>().clone();
+    let world = app.world_mut();
+    let kart = spawn_kart(&mut world.commands(), &asset_server, pos, Quat::IDENTITY);
+    world.flush();
+    kart
+}
+
+fn step(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+// Jump somewhere and give the physics a few frames to report what we touched
+fn teleport(app: &mut App, kart: Entity, pos: Vec3) {
+    let mut entity = app.world_mut().entity_mut(kart);
+    entity.get_mut::<Transform>().unwrap().translation = pos;
+    *entity.get_mut::<Velocity>().unwrap() = Velocity::zero();
+    step(app, 3);
+}
+
+fn stats(app: &App, kart: Entity) -> &PlayerStats {
+    app.world().get::<PlayerStats>(kart).unwrap()
+}
+
+fn count<T: Component>(app: &mut App) -> usize {
+    app.world_mut().query_filtered::<(), With<T>>().iter(app.world()).count()
+}
+
+#[test]
+fn checkpoints_count_in_sequence() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 2);
+    assert_eq!(stats(&app, kart).last_checkpoint, -1);
+
+    teleport(&mut app, kart, CHECKPOINTS[0]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 0);
+
+    // Cutting straight to checkpoint 2 doesn't count
+    teleport(&mut app, kart, CHECKPOINTS[2]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 0);
+
+    teleport(&mut app, kart, CHECKPOINTS[1]);
+    teleport(&mut app, kart, CHECKPOINTS[2]);
+    teleport(&mut app, kart, CHECKPOINTS[3]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 3);
+    assert_eq!(stats(&app, kart).current_lap, 1);
+
+    // Back over the line
+    teleport(&mut app, kart, CHECKPOINTS[0]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 0);
+    assert_eq!(stats(&app, kart).current_lap, 2);
+}
+
+#[test]
+fn checkpoint_zero_does_not_count_twice() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 2);
+
+    teleport(&mut app, kart, CHECKPOINTS[0]);
+    teleport(&mut app, kart, PARKING);
+    teleport(&mut app, kart, CHECKPOINTS[0]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 0);
+    assert_eq!(stats(&app, kart).current_lap, 1);
+}
+
+#[test]
+fn last_lap_finishes_the_race() {
+    let mut app = race_app(1);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 2);
+
+    for checkpoint in CHECKPOINTS.iter().chain([&CHECKPOINTS[0]]) {
+        teleport(&mut app, kart, *checkpoint);
+    }
+    assert!(stats(&app, kart).finished);
+    assert_eq!(app.world().resource::<FinishOrder>().0, vec![kart]);
+
+    // A finished kart stops counting checkpoints
+    teleport(&mut app, kart, CHECKPOINTS[1]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 0);
+}
+
+#[test]
+fn respawn_penalties_count_in_the_finishing_order() {
+    let mut app = race_app(1);
+    let first = add_kart(&mut app, PARKING);
+    let second = add_kart(&mut app, PARKING + Vec3::new(0.0, 0.0, 10.0));
+    step(&mut app, 2);
+    app.world_mut().get_mut::<PlayerStats>(first).unwrap().penalty = 3.0 * RESPAWN_PENALTY;
+
+    for kart in [first, second] {
+        for checkpoint in CHECKPOINTS.iter().chain([&CHECKPOINTS[0]]) {
+            teleport(&mut app, kart, *checkpoint);
+        }
+        teleport(&mut app, kart, PARKING);
+    }
+    assert!(stats(&app, first).finished && stats(&app, second).finished);
+    assert!(stats(&app, first).finish_time.unwrap() > stats(&app, second).finish_time.unwrap());
+    // Home first on the clock, second once the penalty is added
+    assert_eq!(app.world().resource::<FinishOrder>().0, vec![second, first]);
+}
+
+#[test]
+fn coins_are_counted_and_removed() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 2);
+    let coins = count::<Coin>(&mut app);
+
+    teleport(&mut app, kart, Vec3::new(0.0, GROUND_Y, 3.0));
+    teleport(&mut app, kart, Vec3::new(0.0, GROUND_Y, -3.0));
+
+    assert_eq!(stats(&app, kart).coin_count, 2);
+    assert_eq!(count::<Coin>(&mut app), coins - 2);
+}
+
+#[test]
+fn loading_a_track_replaces_its_objects() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 2);
+    let coins = count::<Coin>(&mut app);
+    let boxes = count::<ItemBox>(&mut app);
+
+    teleport(&mut app, kart, Vec3::new(0.0, GROUND_Y, 3.0));
+    assert_eq!(count::<Coin>(&mut app), coins - 1);
+    // Off the coin's spot before it comes back
+    teleport(&mut app, kart, PARKING);
+
+    app.world_mut().send_event(LoadTrack("flat".to_string()));
+    step(&mut app, 3);
+
+    assert_eq!(count::<Coin>(&mut app), coins);
+    assert_eq!(count::<ItemBox>(&mut app), boxes);
+    // Karts aren't part of the track
+    assert!(app.world().get_entity(kart).is_ok());
+}
+
+#[test]
+fn cup_tracks_count_laps_on_their_own_layout() {
+    for track in ["promenade", "paris_bis"] {
+        let mut app = race_app(1);
+        let kart = add_kart(&mut app, PARKING);
+        app.world_mut().send_event(LoadTrack(track.to_string()));
+        step(&mut app, 3);
+
+        let definition = app.world().resource::<TrackDefinition>().clone();
+        let spline = app.world().resource::<TrackSpline>();
+        assert_ne!(definition.checkpoints, track_definition("flat").unwrap().checkpoints, "{}", track);
+        for checkpoint in definition.checkpoints.iter().chain(&definition.item_boxes).chain(&definition.coins) {
+            let (on_line, _) = spline.sample(spline.project(Vec3::from(*checkpoint)));
+            assert!(on_line.xz().distance(Vec3::from(*checkpoint).xz()) < 0.5, "{} {:?}", track, checkpoint);
+        }
+
+        // Everyone starts on the road, just above it
+        for index in 0..4 {
+            let (slot, _) = grid_transform(index, &definition);
+            let (on_line, _) = spline.sample(spline.project(slot));
+            assert!(on_line.xz().distance(slot.xz()) < 1.0, "{} slot {}", track, index);
+            assert!(slot.y > on_line.y && slot.y < on_line.y + 1.5, "{} slot {}", track, index);
+        }
+
+        // Ride height above the racing line, the ground is a model the headless app doesn't load
+        for checkpoint in definition.checkpoints.iter().chain([&definition.checkpoints[0]]) {
+            teleport(&mut app, kart, Vec3::from(*checkpoint) + Vec3::Y * GROUND_Y);
+        }
+        assert!(stats(&app, kart).finished, "{}", track);
+    }
+}
+
+#[test]
+fn reverse_track_counts_checkpoints_backwards() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    app.insert_resource(TrackVariant { mirror: false, reverse: true });
+    app.world_mut().send_event(LoadTrack("flat".to_string()));
+    step(&mut app, 3);
+
+    teleport(&mut app, kart, CHECKPOINTS[0]);
+    // The old next checkpoint is now the last one
+    teleport(&mut app, kart, CHECKPOINTS[1]);
+    assert_eq!(stats(&app, kart).last_checkpoint, 0);
+
+    teleport(&mut app, kart, CHECKPOINTS[3]);
+    teleport(&mut app, kart, CHECKPOINTS[2]);
+    teleport(&mut app, kart, CHECKPOINTS[1]);
+    teleport(&mut app, kart, CHECKPOINTS[0]);
+    assert_eq!(stats(&app, kart).current_lap, 2);
+}
+
+#[test]
+fn mirror_flips_the_track_layout() {
+    let track = track_definition("flat").unwrap();
+    let mirrored = track.clone().with_variant(TrackVariant { mirror: true, reverse: false });
+
+    assert_eq!(mirrored.walls[0].min[0], -track.walls[0].max[0]);
+    assert_eq!(mirrored.walls[0].max[0], -track.walls[0].min[0]);
+    assert_eq!(mirrored.item_boxes[0], [-track.item_boxes[0][0], track.item_boxes[0][1], track.item_boxes[0][2]]);
+    assert_eq!(mirrored.surface_at(Vec3::new(-40.0, 0.0, 0.0)), Some(SurfaceKind::Offroad));
+
+    // Same racing line, driven on the other side
+    let (spline, flipped) = (track.spline(), mirrored.spline());
+    assert_eq!(flipped.points[0], spline.points[0] * Vec3::new(-1.0, 1.0, 1.0));
+    assert!((flipped.length - spline.length).abs() < 1e-3);
+}
+
+#[test]
+fn mirrored_track_colliders_stay_mirrored() {
+    let mut app = race_app(3);
+    app.insert_resource(TrackVariant { mirror: true, reverse: false });
+    app.world_mut().send_event(LoadTrack("flat".to_string()));
+    step(&mut app, 2);
+
+    // A ledge on the +X side of the model, high above the ground slab, flipped like spawn_track does
+    let vertices = vec![Vec3::new(2.0, 10.0, -2.0), Vec3::new(8.0, 10.0, -2.0), Vec3::new(8.0, 10.0, 2.0), Vec3::new(2.0, 10.0, 2.0)];
+    let ledge = Collider::trimesh(vertices, vec![[0, 2, 1], [0, 3, 2]]);
+    app.world_mut()
+        .spawn((Transform::from_scale(Vec3::new(-1.0, 1.0, 1.0)), RigidBody::Fixed, TrackRoot))
+        .with_children(|parent| {
+            parent.spawn((Transform::default(), ledge));
+        });
+    step(&mut app, 5);
+
+    // Checkpoint gates are sensors reaching this high, only solid geometry counts
+    let hit_at = |app: &mut App, x: f32| {
+        let mut contexts = app.world_mut().query::<&RapierContext>();
+        let rapier = contexts.single(app.world());
+        rapier.cast_ray(Vec3::new(x, 20.0, 0.0), Vec3::NEG_Y, 15.0, true, QueryFilter::default().exclude_sensors()).is_some()
+    };
+    assert!(hit_at(&mut app, -5.0));
+    assert!(!hit_at(&mut app, 5.0));
+}
+
+#[test]
+fn item_box_gives_an_item_only_with_empty_hands() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 2);
+    let boxes = count::<ItemBox>(&mut app);
+
+    teleport(&mut app, kart, Vec3::new(5.0, GROUND_Y, 5.0));
+    let item = app.world().get::<HeldItem>(kart).unwrap().item;
+    assert!(item.is_some());
+    assert_eq!(count::<ItemBox>(&mut app), boxes - 1);
+
+    // Hands full: the box still breaks but the item is kept
+    teleport(&mut app, kart, Vec3::new(-5.0, GROUND_Y, 5.0));
+    assert_eq!(app.world().get::<HeldItem>(kart).unwrap().item, item);
+    assert_eq!(count::<ItemBox>(&mut app), boxes - 2);
+}
+
+#[test]
+fn arena_item_boxes_come_back() {
+    let mut app = race_app(3);
+    app.world_mut().send_event(LoadTrack("arena".to_string()));
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 3);
+    let boxes = count::<ItemBox>(&mut app);
+
+    teleport(&mut app, kart, Vec3::new(12.0, GROUND_Y, 0.0));
+    assert!(app.world().get::<HeldItem>(kart).unwrap().item.is_some());
+    assert_eq!(count::<ItemBox>(&mut app), boxes);
+    assert_eq!(count::<BrokenItemBox>(&mut app), 1);
+
+    teleport(&mut app, kart, Vec3::new(0.0, GROUND_Y, -4.0));
+    step(&mut app, 200);
+    assert_eq!(count::<BrokenItemBox>(&mut app), 0);
+}
+
+#[test]
+fn falling_off_the_track_respawns_on_the_racing_line() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    // Settle on the ground so it becomes the safe position
+    step(&mut app, 30);
+
+    // Past the edge of the track, nothing to land on
+    teleport(&mut app, kart, Vec3::new(80.0, -2.0, 80.0));
+
+    let mut fell = false;
+    for _ in 0..300 {
+        app.update();
+        if app.world().get::<Respawn>(kart).is_some() {
+            fell = true;
+            break;
+        }
+    }
+    assert!(fell, "kart never fell below y = -10");
+
+    step(&mut app, (RESPAWN_TIME * 60.0) as usize + 30);
+    assert!(app.world().get::<Respawn>(kart).is_none());
+    assert_on_racing_line(&app, kart);
+    assert_eq!(stats(&app, kart).penalty, RESPAWN_PENALTY);
+}
+
+#[test]
+fn reset_button_respawns_behind_with_controls_locked() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    // Past the countdown
+    step(&mut app, 200);
+
+    let reset = KartInput { reset: true, throttle: 1.0, ..default() };
+    let throttle = KartInput { throttle: 1.0, ..default() };
+    let mut script = vec![reset];
+    script.extend([throttle; 9]);
+    // Mashing reset during the sequence
+    script.extend([reset, throttle]);
+    app.world_mut().entity_mut(kart).insert(ScriptedInput::new(script));
+    app.update();
+    assert!(app.world().get::<Respawn>(kart).is_some());
+
+    // Throttle held, but nobody drives while Lakitu has the kart
+    step(&mut app, (RESPAWN_FADE_OUT * 60.0) as usize + 5);
+    assert_eq!(app.world().get::<Kart>(kart).unwrap().speed, 0.0);
+    assert_on_racing_line(&app, kart);
+
+    // A second reset during the sequence doesn't stack penalties
+    step(&mut app, (RESPAWN_TIME * 60.0) as usize);
+    assert!(app.world().get::<Respawn>(kart).is_none());
+    assert_eq!(stats(&app, kart).penalty, RESPAWN_PENALTY);
+}
+
+// Over the spline, facing along it
+fn assert_on_racing_line(app: &App, kart: Entity) {
+    let spline = app.world().resource::<TrackSpline>();
+    let transform = app.world().get::<Transform>(kart).unwrap();
+    let (point, tangent) = spline.sample(spline.project(transform.translation));
+    assert!(transform.translation.xz().distance(point.xz()) < 1.0, "kart at {} after the respawn", transform.translation);
+    assert!(transform.forward().xz().dot(tangent.xz().normalize()) > 0.9, "kart facing {:?} instead of {tangent}", transform.forward());
+}
+
+#[test]
+fn scripted_throttle_only_drives_once_racing() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    app.world_mut().entity_mut(kart).insert(ScriptedInput::hold(KartInput { throttle: 1.0, ..default() }));
+
+    // Still on the grid during the countdown
+    step(&mut app, 60);
+    assert_eq!(*app.world().resource::<State<RacePhase>>().get(), RacePhase::Countdown);
+    let start = app.world().get::<Transform>(kart).unwrap().translation;
+    assert!(start.xz().distance(PARKING.xz()) < 0.5);
+
+    step(&mut app, 200);
+    assert_eq!(*app.world().resource::<State<RacePhase>>().get(), RacePhase::Racing);
+    let end = app.world().get::<Transform>(kart).unwrap().translation;
+    // Karts face -Z
+    assert!(end.z < start.z - 5.0, "kart only moved from {start} to {end}");
+}
+
+#[test]
+fn kart_knows_when_it_is_on_the_ground() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING + Vec3::Y * 3.0);
+    app.update();
+    assert!(!app.world().get::<Kart>(kart).unwrap().grounded);
+
+    step(&mut app, 60);
+    let state = app.world().get::<Kart>(kart).unwrap();
+    assert!(state.grounded);
+    assert!(state.ground_normal.distance(Vec3::Y) < 0.01);
+    assert!(state.last_safe_pos.y < 1.0, "safe position {} recorded in the air", state.last_safe_pos);
+}
+
+#[test]
+fn surfaces_from_the_track_definition() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 30);
+    assert_eq!(app.world().get::<Kart>(kart).unwrap().surface, SurfaceKind::Road);
+
+    teleport(&mut app, kart, Vec3::new(40.0, GROUND_Y, 0.0));
+    assert_eq!(app.world().get::<Kart>(kart).unwrap().surface, SurfaceKind::Offroad);
+
+    teleport(&mut app, kart, Vec3::new(-40.0, GROUND_Y, 0.0));
+    let state = app.world().get::<Kart>(kart).unwrap();
+    assert_eq!(state.surface, SurfaceKind::BoostPad);
+    assert!(state.is_boosting);
+}
+
+#[test]
+fn ramps_launch_the_kart_and_allow_tricks() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, RAMP);
+    step(&mut app, 10);
+    assert!(app.world().get::<Kart>(kart).unwrap().trick_ready);
+
+    step(&mut app, 10);
+    assert!(app.world().get::<Transform>(kart).unwrap().translation.y > GROUND_Y + 0.5);
+}
+
+#[test]
+fn trick_off_a_ramp_boosts_on_landing() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    // Past the countdown
+    step(&mut app, 200);
+
+    // Airborne by the time the button goes down
+    let mut script = vec![KartInput::default(); 12];
+    script.extend([KartInput { jump: true, ..default() }; 3]);
+    script.push(KartInput::default());
+    app.world_mut().entity_mut(kart).insert(ScriptedInput::new(script));
+    teleport(&mut app, kart, RAMP);
+
+    let mut tricked = false;
+    let mut boosted_landing = false;
+    for _ in 0..180 {
+        app.update();
+        let state = app.world().get::<Kart>(kart).unwrap();
+        tricked |= state.tricked;
+        if tricked && state.grounded {
+            boosted_landing = state.is_boosting;
+            break;
+        }
+    }
+    assert!(tricked, "no trick off the ramp");
+    assert!(boosted_landing, "no boost on landing");
+}
+
+#[test]
+fn kill_volumes_respawn_karts() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    step(&mut app, 30);
+
+    // The flat track's pit, on solid ground
+    teleport(&mut app, kart, Vec3::new(-40.0, GROUND_Y, -30.0));
+    assert!(app.world().get::<Respawn>(kart).is_some());
+
+    step(&mut app, (RESPAWN_TIME * 60.0) as usize + 30);
+    assert_on_racing_line(&app, kart);
+}
+
+#[test]
+fn driving_into_a_wall_bounces_back() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, Vec3::new(30.0, GROUND_Y, 25.0));
+    // Past the countdown
+    step(&mut app, 200);
+    app.world_mut().entity_mut(kart).insert(ScriptedInput::hold(KartInput { throttle: 1.0, ..default() }));
+
+    // The flat track's wall spans z 9.5..10.5
+    let mut hit = false;
+    let mut bounced = false;
+    for _ in 0..180 {
+        app.update();
+        hit |= !app.world().resource::<Events<WallHit>>().is_empty();
+        let (transform, velocity) = (app.world().get::<Transform>(kart).unwrap(), app.world().get::<Velocity>(kart).unwrap());
+        assert!(transform.translation.z > 10.5, "kart went through the wall to {}", transform.translation);
+        bounced |= hit && velocity.linvel.z > 0.5;
+        if bounced {
+            break;
+        }
+    }
+    assert!(hit, "no wall hit");
+    assert!(bounced, "kart didn't bounce off the wall");
+}
+
+#[test]
+fn heavy_karts_shove_light_karts_further() {
+    let mut app = race_app(3);
+    let heavy = add_kart(&mut app, PARKING);
+    let light = add_kart(&mut app, PARKING + Vec3::X * 1.2);
+    // After the builds have set their stats
+    app.update();
+    app.world_mut().entity_mut(heavy).insert(KartStats { weight: 2.0, ..default() });
+    app.world_mut().entity_mut(light).insert(KartStats { weight: 0.5, ..default() });
+    step(&mut app, 60);
+
+    let heavy_moved = (app.world().get::<Transform>(heavy).unwrap().translation - PARKING).with_y(0.0).length();
+    let light_moved = (app.world().get::<Transform>(light).unwrap().translation - PARKING - Vec3::X * 1.2).with_y(0.0).length();
+    assert!(light_moved > 0.2, "karts didn't bump");
+    assert!(light_moved > heavy_moved * 2.0, "light kart moved {light_moved}, heavy kart {heavy_moved}");
+}
+
+#[test]
+fn kart_stats_follow_the_build() {
+    let mut app = race_app(3);
+    let kart = add_kart(&mut app, PARKING);
+    app.update();
+    let standard = app.world().get::<KartStats>(kart).unwrap().clone();
+    assert_eq!(standard.speed, app.world().resource::<KartCatalog>().stats(&KartBuild::default()).speed);
+
+    let build = KartBuild { character: "Toad".to_string(), kart: "Badwagon".to_string(), wheels: "Monster".to_string() };
+    app.world_mut().entity_mut(kart).insert(build.clone());
+    app.update();
+    let stats = app.world().get::<KartStats>(kart).unwrap();
+    let expected = app.world().resource::<KartCatalog>().stats(&build);
+    assert_eq!(stats.weight, expected.weight);
+    assert_eq!(stats.handling, expected.handling);
+    assert_ne!(stats.weight, standard.weight);
+
+    // Parts missing from the data file change nothing
+    let unknown = KartBuild { character: "Nobody".to_string(), ..default() };
+    assert_eq!(app.world().resource::<KartCatalog>().stats(&unknown).speed, standard.speed);
+}
+
+#[test]
+fn ghost_replays_between_samples() {
+    let frame = |time: f32, x: f32| GhostFrame { time, pos: [x, 0.0, 0.0], rot: Quat::IDENTITY.to_array() };
+    let record = TimeTrialRecord { total: 1.0, splits: vec![0.5], ghost: vec![frame(0.0, 0.0), frame(0.1, 1.0), frame(0.2, 3.0)] };
+
+    assert!((record.ghost_at(0.05).unwrap().translation.x - 0.5).abs() < 1e-4);
+    assert!((record.ghost_at(0.15).unwrap().translation.x - 2.0).abs() < 1e-4);
+    // Holds its last spot once the run is over
+    assert_eq!(record.ghost_at(5.0).unwrap().translation.x, 3.0);
+    assert!(TimeTrialRecord::default().ghost_at(0.0).is_none());
+}